@@ -1,30 +1,92 @@
+#import bevy_pbr::{
+    mesh_functions,
+    view_transformations::position_world_to_clip,
+}
+
 // Bindings
 @group(2) @binding(0) var tile_texture: texture_2d<f32>;
 @group(2) @binding(1) var tile_sampler: sampler;
-@group(2) @binding(2) var<uniform> tile_index: vec2<u32>;
-@group(2) @binding(3) var<uniform> tile_count: vec2<u32>;
+@group(2) @binding(2) var<uniform> atlas: AtlasLayout;
+
+// Where the cells of the sheet are, in texture coordinates
+struct AtlasLayout {
+    columns: u32,
+    origin: vec2<f32>,
+    stride: vec2<f32>,
+    size: vec2<f32>,
+};
+
+// MeshTag layout, kept in sync with tile_material.rs
+const TAG_INDEX_MASK: u32 = 0x00ffffffu;
+const TAG_REMEMBERED: u32 = 0x01000000u;
+const TAG_HIGHLIGHTED: u32 = 0x02000000u;
+
+const REMEMBERED_TINT: vec3<f32> = vec3(0.35, 0.35, 0.45);
+const HIGHLIGHT_GLOW: vec3<f32> = vec3(0.3, 0.3, 0.0);
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+#ifdef VERTEX_COLORS
+    @location(5) color: vec4<f32>,
+#endif
+};
 
 // Vertex output structure
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) tag: u32,
 };
 
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    let world_position = mesh_functions::mesh_position_local_to_world(
+        world_from_local,
+        vec4(vertex.position, 1.0),
+    );
+    out.position = position_world_to_clip(world_position.xyz);
+    out.uv = vertex.uv;
+#ifdef VERTEX_COLORS
+    // Chunk meshes carry atlas UVs already, and their flags in the color
+    out.tag = select(0u, TAG_REMEMBERED, vertex.color.r > 0.5)
+        | select(0u, TAG_HIGHLIGHTED, vertex.color.g > 0.5);
+#else
+    out.tag = mesh_functions::get_tag(vertex.instance_index);
+#endif
+    return out;
+}
+
 // Fragment shader
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
-    let tile_size = vec2(1.0 / f32(tile_count.x), 1.0 / f32(tile_count.y));
-
-    // Keep in.uv as-is (0..1 range per face)
-    let uv_in_tile = in.uv;
+#ifdef VERTEX_COLORS
+    let final_uv = in.uv;
+#else
+    // Stay just inside the face (0..1 range) so nearest sampling never picks
+    // up the neighbouring cell
+    let uv_in_tile = clamp(in.uv, vec2(0.001), vec2(0.999));
 
-    // Calculate tile origin based on tile_index
-    let tile_origin = vec2(
-        f32(tile_index.x) * tile_size.x,
-        f32(tile_index.y) * tile_size.y,
-    );
+    // Calculate tile origin based on the cell index in the tag
+    let tile_index = in.tag & TAG_INDEX_MASK;
+    let cell = vec2(f32(tile_index % atlas.columns), f32(tile_index / atlas.columns));
+    let tile_origin = atlas.origin + cell * atlas.stride;
 
-    let final_uv = tile_origin + uv_in_tile * tile_size;
+    let final_uv = tile_origin + uv_in_tile * atlas.size;
+#endif
 
-    return textureSample(tile_texture, tile_sampler, final_uv);
+    var color = textureSample(tile_texture, tile_sampler, final_uv);
+    if color.a < 0.5 {
+        discard;
+    }
+    if (in.tag & TAG_REMEMBERED) != 0u {
+        color = vec4(color.rgb * REMEMBERED_TINT, color.a);
+    }
+    if (in.tag & TAG_HIGHLIGHTED) != 0u {
+        color = vec4(color.rgb + HIGHLIGHT_GLOW, color.a);
+    }
+    return color;
 }
@@ -1,67 +1,304 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use bevy::{
-    asset::RenderAssetUsages,
+    asset::io::file::FileAssetReader,
     prelude::*,
-    render::render_resource::{Extent3d, TextureDimension},
+    render::{
+        mesh::MeshTag,
+        render_resource::{TextureFormat, TextureSampleType},
+    },
 };
 
 use crate::{
     AppState,
     components::{attributes::Moving, tiles::*},
     systems::tile_map::util::TILE_SIZE,
+    tile_material::{AtlasLayout, TAG_INDEX_MASK, TileMaterial},
 };
+use serde::Deserialize;
+
+// Relative to the asset folder
+const DEFAULT_SHEETS_PATH: &str = "tilesheets/sheets.ron";
+
+/// Where the asset server finds `path`, for files that are read before it
+/// runs. Like the asset server, this doesn't depend on the working directory.
+pub fn asset_file(path: &str) -> PathBuf {
+    FileAssetReader::new(AssetPlugin::default().file_path)
+        .root_path()
+        .join(path)
+}
+
+/// Where `path` is found next to the asset folder, for game files that
+/// aren't assets.
+pub fn game_file(path: &str) -> PathBuf {
+    FileAssetReader::get_base_path().join(path)
+}
+
+/// The name a tilesheet is declared under in the sheet manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct SheetId(Cow<'static, str>);
+
+impl SheetId {
+    pub const fn new(name: &'static str) -> Self {
+        SheetId(Cow::Borrowed(name))
+    }
+}
+
+impl From<&str> for SheetId {
+    fn from(name: &str) -> Self {
+        SheetId(Cow::Owned(name.to_string()))
+    }
+}
+
+impl fmt::Display for SheetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
-pub enum TileSheetType {
-    World,
-    Monsters,
+/// One entry of the sheet manifest.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SheetDefinition {
+    /// Image path, relative to the assets folder.
+    pub path: String,
+    #[serde(default)]
+    pub layout: SheetLayout,
 }
 
-#[derive(Resource, Default)]
-pub struct SpriteCache {
-    pub sprites: HashMap<(TileSheetType, UVec2), Handle<Image>>,
+/// Every tilesheet sprites can be drawn from, as declared in the sheet
+/// manifest. Adding a sheet only takes a new entry there.
+#[derive(Resource, Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct TileSheets(pub HashMap<SheetId, SheetDefinition>);
+
+impl TileSheets {
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// Reads the manifest at `path`. Without one there is nothing to draw
+    /// tiles with, so problems are logged as errors.
+    pub fn load(path: &Path) -> Self {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| TileSheets::parse(&source).map_err(|err| err.to_string()));
+        parsed.unwrap_or_else(|err| {
+            error!(
+                "Can't read the tilesheet manifest {}: {err}",
+                path.display()
+            );
+            TileSheets::default()
+        })
+    }
+
+    /// Loads the manifest given with `--tilesheets`, or the shipped one.
+    pub fn from_args() -> Self {
+        let path = crate::systems::tile_map::generation::arg_value("--tilesheets")
+            .map_or_else(|| asset_file(DEFAULT_SHEETS_PATH), PathBuf::from);
+        TileSheets::load(&path)
+    }
+
+    /// Matches an image referenced from an external tool by file name, so
+    /// relative paths like `../tilesheets/tiny_dungeon_world.png` resolve.
+    pub fn find_image(&self, image: &str) -> Option<SheetId> {
+        let file_name = image.rsplit(['/', '\\']).next()?;
+        self.0
+            .iter()
+            .find(|(_, sheet)| sheet.path.rsplit('/').next() == Some(file_name))
+            .map(|(id, _)| id.clone())
+    }
 }
 
+/// How a tilesheet is cut into cells, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SheetLayout {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Border around the whole grid of cells.
+    pub margin: u32,
+    /// Gap between neighbouring cells.
+    pub spacing: u32,
+}
+
+impl Default for SheetLayout {
+    fn default() -> Self {
+        SheetLayout {
+            tile_width: TILE_SIZE as u32,
+            tile_height: TILE_SIZE as u32,
+            margin: 0,
+            spacing: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SheetError {
+    EmptyTiles,
+    /// The cells and gaps don't add up to the image's width or height.
+    Misfit {
+        axis: &'static str,
+        length: u32,
+        tile: u32,
+    },
+    UnsupportedFormat(TextureFormat),
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SheetError::EmptyTiles => write!(f, "tile width and height must be at least 1"),
+            SheetError::Misfit { axis, length, tile } => write!(
+                f,
+                "{axis} of {length}px doesn't fit a whole number of {tile}px tiles with the \
+                 given margin and spacing"
+            ),
+            SheetError::UnsupportedFormat(format) => {
+                write!(f, "{format:?} textures can't be sampled as colors")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+impl SheetLayout {
+    /// Columns and rows of cells in a sheet of `size` pixels.
+    pub fn grid(&self, size: UVec2) -> Result<UVec2, SheetError> {
+        let count = |axis, length: u32, tile: u32| {
+            let misfit = SheetError::Misfit { axis, length, tile };
+            // n tiles take up 2 * margin + n * tile + (n - 1) * spacing. Sums
+            // too large for a u32 can't fit any image either.
+            let inner = length
+                .checked_add(self.spacing)
+                .zip(self.margin.checked_mul(2))
+                .and_then(|(total, margins)| total.checked_sub(margins))
+                .ok_or(misfit.clone())?;
+            let stride = tile.checked_add(self.spacing).ok_or(misfit.clone())?;
+            if inner < stride || inner % stride != 0 {
+                return Err(misfit);
+            }
+            Ok(inner / stride)
+        };
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(SheetError::EmptyTiles);
+        }
+        Ok(UVec2::new(
+            count("width", size.x, self.tile_width)?,
+            count("height", size.y, self.tile_height)?,
+        ))
+    }
+
+    /// Where the cells of a sheet of `size` pixels are, in texture coordinates.
+    pub fn atlas(&self, size: UVec2) -> Result<AtlasLayout, SheetError> {
+        let grid = self.grid(size)?;
+        let size = size.as_vec2();
+        let tile = UVec2::new(self.tile_width, self.tile_height).as_vec2();
+        Ok(AtlasLayout {
+            columns: grid.x,
+            origin: Vec2::splat(self.margin as f32) / size,
+            stride: (tile + self.spacing as f32) / size,
+            size: tile / size,
+        })
+    }
+}
+
+/// Checks that `image` can be cut up with `layout` and drawn by a
+/// `TileMaterial`, whatever its pixel format.
+fn sheet_atlas(image: &Image, layout: &SheetLayout) -> Result<(AtlasLayout, UVec2), SheetError> {
+    let format = image.texture_descriptor.format;
+    if format.sample_type(None, None) != Some(TextureSampleType::Float { filterable: true }) {
+        return Err(SheetError::UnsupportedFormat(format));
+    }
+    Ok((layout.atlas(image.size())?, layout.grid(image.size())?))
+}
+
+/// A sheet's material, where its cells are and how many there are.
+struct SheetMaterial {
+    material: Handle<TileMaterial>,
+    atlas: AtlasLayout,
+    grid: UVec2,
+    texel: Vec2,
+}
+
+/// One material per tilesheet and the cube mesh every tile shares.
+#[derive(Resource)]
+pub struct TileAssets {
+    materials: HashMap<SheetId, SheetMaterial>,
+    mesh: Handle<Mesh>,
+}
+
+impl TileAssets {
+    /// The material for `sprite`'s sheet and the tag that selects its cell.
+    pub fn sprite(&self, sprite: &SheetSprite) -> Option<(Handle<TileMaterial>, MeshTag)> {
+        let sheet = self.materials.get(&sprite.tilesheet)?;
+        // tilesheet_x is the row and tilesheet_y the column
+        let (row, column) = (sprite.tilesheet_x, sprite.tilesheet_y);
+        let index = row * sheet.grid.x + column;
+        if row >= sheet.grid.y || column >= sheet.grid.x || index > TAG_INDEX_MASK {
+            return None;
+        }
+        Some((sheet.material.clone(), MeshTag(index)))
+    }
+
+    pub fn material(&self, sheet: &SheetId) -> Option<Handle<TileMaterial>> {
+        self.materials
+            .get(sheet)
+            .map(|sheet| sheet.material.clone())
+    }
+
+    /// Where `sprite`'s cell sits in its sheet, in texture coordinates. The
+    /// rect stays half a texel inside the cell so that its neighbours never
+    /// bleed in.
+    pub fn uv_rect(&self, sprite: &SheetSprite) -> Option<Rect> {
+        let sheet = self.materials.get(&sprite.tilesheet)?;
+        let (row, column) = (sprite.tilesheet_x, sprite.tilesheet_y);
+        if row >= sheet.grid.y || column >= sheet.grid.x {
+            return None;
+        }
+        let cell = sheet.atlas.cell(row, column);
+        let inset = sheet.texel / 2.0;
+        Some(Rect::from_corners(cell.min + inset, cell.max - inset))
+    }
+}
+
+/// The image of every sheet in the manifest and how to cut it up.
 #[derive(Resource)]
 pub struct AssetManager {
-    sheets: HashMap<TileSheetType, Handle<Image>>,
+    sheets: HashMap<SheetId, (Handle<Image>, SheetLayout)>,
 }
 
+type UnattachedSprite<'a> = (Entity, &'a SheetSprite, &'a MapPosition, Option<&'a Layer>);
+type NotTerrain = (Without<Mesh3d>, Without<Walkable>, Without<Blocking>);
+
+/// Gives every sprite that isn't part of the terrain its own cube. Terrain is
+/// baked into chunk meshes instead.
 pub fn attach_sprites(
     mut commands: Commands,
-    sprite_cache: Res<SpriteCache>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    query: Query<(Entity, &SheetSprite, &MapPosition, Option<&Layer>), Without<Mesh3d>>,
+    tile_assets: Res<TileAssets>,
+    query: Query<UnattachedSprite, NotTerrain>,
 ) {
     for (entity, sheet_sprite, map_position, layer) in query.iter() {
-        let index = UVec2::new(sheet_sprite.tilesheet_x, sheet_sprite.tilesheet_y);
-        let key = (sheet_sprite.tilesheet.clone(), index);
-
-        if let Some(image_handle) = sprite_cache.sprites.get(&key) {
-            let cube_mesh = meshes.add(Cuboid {
-                half_size: Vec3::splat(0.5),
-                ..Default::default()
-            });
-
-            let material = materials.add(StandardMaterial {
-                base_color_texture: Some(image_handle.clone()),
-                ..Default::default()
-            });
-
-            commands.entity(entity).insert((
-                Transform::from_xyz(
-                    map_position.x as f32,
-                    map_position.y as f32,
-                    layer.map(|l| l.0 as f32).unwrap_or(0.0),
-                ),
-                Mesh3d(cube_mesh),
-                MeshMaterial3d(material),
-            ));
-        } else {
-            //warn!("Sprite not found in cache for {:?}", key);
-        }
+        let Some((material, tag)) = tile_assets.sprite(sheet_sprite) else {
+            continue;
+        };
+
+        commands.entity(entity).insert((
+            Transform::from_xyz(
+                map_position.x as f32,
+                map_position.y as f32,
+                layer.map(|l| l.0 as f32).unwrap_or(0.0),
+            ),
+            Mesh3d(tile_assets.mesh.clone()),
+            MeshMaterial3d(material),
+            tag,
+        ));
     }
 }
 pub fn sync_transform_to_map_position(
@@ -85,93 +322,154 @@ pub fn sync_transform_to_map_position(
     }
 }
 
-pub fn setup_asset_manager(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let mut asset_manager = AssetManager {
-        sheets: HashMap::new(),
-    };
-
-    asset_manager.sheets.insert(
-        TileSheetType::World,
-        asset_server.load("tilesheets/tiny_dungeon_world.png"),
-    );
-
-    asset_manager.sheets.insert(
-        TileSheetType::Monsters,
-        asset_server.load("tilesheets/tiny_dungeon_monsters.png"),
-    );
-
-    commands.insert_resource(asset_manager);
-    commands.insert_resource(SpriteCache::default());
+pub fn setup_asset_manager(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    tile_sheets: Res<TileSheets>,
+) {
+    let sheets = tile_sheets
+        .0
+        .iter()
+        .map(|(id, sheet)| (id.clone(), (asset_server.load(&sheet.path), sheet.layout)))
+        .collect();
+    commands.insert_resource(AssetManager { sheets });
 }
 
-pub fn slice_tilesheets_into_cache(
-    mut images: ResMut<Assets<Image>>,
-    mut sprite_cache: ResMut<SpriteCache>,
+/// Waits for every tilesheet to load, then sets up its material. Sheets that
+/// don't match their layout are left out, and so are their sprites.
+pub fn build_tile_materials(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
     asset_manager: Res<AssetManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TileMaterial>>,
     mut next_state: ResMut<NextState<AppState>>,
 ) {
-    info!("slice_tilesheets_into_cache");
-
-    for (sheet_type, handle) in &asset_manager.sheets {
-        info!("handle and sheet: ({:?}, {:?})", sheet_type, handle);
-
-        if let Some(image) = images.get(handle) {
-            let tile_size_u32 = TILE_SIZE as u32;
-
-            let columns = image.texture_descriptor.size.width / tile_size_u32;
-            let rows = image.texture_descriptor.size.height / tile_size_u32;
-            info!("colrows: ({:?}, {:?})", columns, rows);
-            let cloned_image = image.clone();
-            let texture_width = cloned_image.texture_descriptor.size.width;
-            if let Some(data) = &cloned_image.data {
-                let layout = image.texture_descriptor.format;
-
-                for y in 0..rows {
-                    for x in 0..columns {
-                        let mut tile_data = Vec::new();
-
-                        for row in 0..tile_size_u32 {
-                            let src_row = y * tile_size_u32 + row;
-                            let start = (src_row * texture_width + x * tile_size_u32) * 4;
-                            let end = start + tile_size_u32 * 4;
-
-                            tile_data.extend_from_slice(&data[start as usize..end as usize]);
-                        }
-
-                        let tile_image = Image::new(
-                            Extent3d {
-                                width: tile_size_u32,
-                                height: tile_size_u32,
-                                depth_or_array_layers: 1,
-                            },
-                            TextureDimension::D2,
-                            tile_data,
-                            layout,
-                            RenderAssetUsages::RENDER_WORLD,
-                        );
-
-                        let handle = images.add(tile_image);
-                        sprite_cache
-                            .sprites
-                            .insert((sheet_type.clone(), UVec2::new(y, x)), handle);
-                        info!(
-                            "Inserted sprite: ({:?}, {:?})",
-                            sheet_type,
-                            UVec2::new(x, y)
-                        );
-                    }
-                }
-            } else {
-                warn!("Image data not loaded yet for sheet: {:?}", sheet_type);
+    let loaded: Option<Vec<_>> = asset_manager
+        .sheets
+        .iter()
+        .map(|(id, (handle, layout))| Some((id, handle, layout, images.get(handle)?)))
+        .collect();
+    let Some(loaded) = loaded else {
+        return;
+    };
+
+    let mut tile_materials = HashMap::new();
+    for (id, handle, layout, image) in loaded {
+        let (atlas, grid) = match sheet_atlas(image, layout) {
+            Ok(layout) => layout,
+            Err(err) => {
+                error!("Can't use the {id} tilesheet: {err}");
                 continue;
             }
-        } else {
-            warn!("Failed getting image data for sheet: {:?}", sheet_type);
-            continue;
-        }
+        };
+        info!("{id} sheet: {} x {} tiles", grid.x, grid.y);
+        let material = materials.add(TileMaterial {
+            texture: handle.clone(),
+            atlas,
+        });
+        let sheet = SheetMaterial {
+            material,
+            atlas,
+            grid,
+            texel: Vec2::ONE / image.size().as_vec2(),
+        };
+        tile_materials.insert(id.clone(), sheet);
     }
 
+    commands.insert_resource(TileAssets {
+        materials: tile_materials,
+        mesh: meshes.add(Cuboid {
+            half_size: Vec3::splat(0.5),
+        }),
+    });
     next_state.set(AppState::Game);
-    info!("Finished slicing all tilesheets.");
-    //commands.remove_resource::<AssetManager>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_sheets_are_cut_into_whole_tiles() {
+        let layout = SheetLayout::default();
+        assert_eq!(layout.grid(UVec2::new(256, 304)), Ok(UVec2::new(16, 19)));
+        let atlas = layout.atlas(UVec2::new(64, 32)).unwrap();
+        assert_eq!(atlas.cell(1, 2), Rect::new(0.5, 0.5, 0.75, 1.0));
+    }
+
+    #[test]
+    fn margins_and_spacing_are_skipped() {
+        let layout = SheetLayout {
+            tile_width: 8,
+            tile_height: 32,
+            margin: 1,
+            spacing: 2,
+        };
+        // 1 + 3 * 8 + 2 * 2 + 1 and 1 + 32 + 1
+        let size = UVec2::new(30, 34);
+        assert_eq!(layout.grid(size), Ok(UVec2::new(3, 1)));
+        let cell = layout.atlas(size).unwrap().cell(0, 1);
+        assert_eq!(cell.min * size.as_vec2(), Vec2::new(11.0, 1.0));
+        assert_eq!(cell.max * size.as_vec2(), Vec2::new(19.0, 33.0));
+    }
+
+    #[test]
+    fn shipped_sheets_fit_their_layouts() {
+        let sheets = TileSheets::parse(include_str!("../assets/tilesheets/sheets.ron")).unwrap();
+        for (id, sheet) in &sheets.0 {
+            let png = std::fs::read(Path::new("assets").join(&sheet.path)).unwrap();
+            // Width and height lead the IHDR chunk, right after the signature
+            let dimension = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+            let size = UVec2::new(dimension(16), dimension(20));
+            assert!(
+                sheet.layout.grid(size).is_ok(),
+                "{id} doesn't fit its layout"
+            );
+        }
+        assert_eq!(
+            sheets.find_image("../tilesheets/tiny_dungeon_items.png"),
+            Some(SheetId::new("items"))
+        );
+    }
+
+    #[test]
+    fn leftover_pixels_are_an_error() {
+        let layout = SheetLayout::default();
+        assert_eq!(
+            layout.grid(UVec2::new(216, 42)),
+            Err(SheetError::Misfit {
+                axis: "width",
+                length: 216,
+                tile: 16
+            })
+        );
+        let empty = SheetLayout {
+            tile_width: 0,
+            ..default()
+        };
+        assert_eq!(empty.grid(UVec2::new(16, 16)), Err(SheetError::EmptyTiles));
+    }
+
+    #[test]
+    fn huge_margins_and_spacing_are_misfits() {
+        let size = UVec2::new(16, 16);
+        for layout in [
+            SheetLayout {
+                margin: u32::MAX,
+                ..default()
+            },
+            SheetLayout {
+                spacing: u32::MAX,
+                ..default()
+            },
+            SheetLayout {
+                tile_width: u32::MAX,
+                spacing: 1,
+                ..default()
+            },
+        ] {
+            assert!(matches!(layout.grid(size), Err(SheetError::Misfit { .. })));
+        }
+    }
 }
@@ -1,11 +1,29 @@
 use bevy::prelude::*;
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone, Copy)]
 pub struct Health {
     pub current: f32,
     pub max: f32,
 }
 
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Melee stats; damage dealt is the attacker's `attack` minus the defender's
+/// `defense`, but always at least one point.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CombatStats {
+    pub attack: f32,
+    pub defense: f32,
+}
+
 #[derive(Component)]
 pub struct Moving {
     pub speed: f32, // tiles/sec
@@ -20,3 +38,20 @@ impl Default for Moving {
         }
     }
 }
+
+/// Actors accumulate `speed` energy every tick and act once they have
+/// saved up enough for an action.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Energy {
+    pub current: u32,
+    pub speed: u32,
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Self {
+            current: 0,
+            speed: 10,
+        }
+    }
+}
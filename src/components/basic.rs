@@ -1,10 +1,69 @@
 use bevy::prelude::*;
 
+/// A monster and how it behaves; see `systems::ai`.
+#[derive(Component, Debug, Clone)]
+pub struct Monster {
+    pub state: AiState,
+    /// How many tiles away the monster notices the player.
+    pub sight_range: u32,
+    /// Flees once health drops below this fraction of its maximum.
+    pub flee_below: f32,
+    /// Whether the monster roams around when it has nothing to chase.
+    pub wanders: bool,
+}
+
+impl Default for Monster {
+    fn default() -> Self {
+        Monster {
+            state: AiState::Idle,
+            sight_range: 6,
+            flee_below: 0.0,
+            wanders: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AiState {
+    #[default]
+    Idle,
+    Wander,
+    Chase,
+    Flee,
+}
+
 #[derive(Component)]
 pub struct PathMarker;
 
+/// Left behind where something died.
+#[derive(Component)]
+pub struct Corpse;
+
+/// Which side an actor fights on; bumping into a hostile actor attacks it.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Faction {
+    Heroes,
+    Monsters,
+}
+
+impl Faction {
+    pub fn is_hostile_to(self, other: Faction) -> bool {
+        self != other
+    }
+}
+
 #[derive(Component)]
 pub struct Player;
 
+/// Marks actors whose turn it currently is.
 #[derive(Component)]
-pub struct Visible;
+pub struct TakingTurn;
+
+/// Fog of war state, updated from the player's field of view.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visible {
+    #[default]
+    Unseen,
+    Remembered,
+    InView,
+}
@@ -1,33 +1,56 @@
-use crate::asset_manager::TileSheetType;
+use crate::{asset_manager::SheetId, sprite_catalog::SpriteCatalog};
 use bevy::prelude::*;
+use rand::Rng;
 
 #[derive(Component)]
 pub struct Blocking;
 
+#[derive(Component)]
+pub struct Door;
+
 #[derive(Component)]
 pub struct Highlight;
 
 #[derive(Component, Default)]
 pub struct Layer(pub u32);
 
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct MapPosition {
     pub x: usize,
     pub y: usize,
 }
-impl Default for MapPosition {
-    fn default() -> Self {
-        MapPosition { x: 0, y: 0 }
-    }
-}
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
 pub struct SheetSprite {
-    pub tilesheet: TileSheetType,
+    pub tilesheet: SheetId,
     pub tilesheet_x: u32,
     pub tilesheet_y: u32,
 }
 
+impl SheetSprite {
+    /// Stands in for sprites missing from the catalog. No sheet goes by its
+    /// name, so it is never drawn.
+    pub const MISSING: SheetSprite = SheetSprite {
+        tilesheet: SheetId::new(""),
+        tilesheet_x: 0,
+        tilesheet_y: 0,
+    };
+
+    /// The sprite called `name` in `catalog`.
+    pub fn named(catalog: &SpriteCatalog, name: &str) -> SheetSprite {
+        catalog
+            .get(name)
+            .map_or(SheetSprite::MISSING, |sprite| sprite.sprite())
+    }
+
+    /// The sprite called `name`, or one of its variants.
+    pub fn any_named(catalog: &SpriteCatalog, name: &str, rng: &mut impl Rng) -> SheetSprite {
+        catalog
+            .get(name)
+            .map_or(SheetSprite::MISSING, |sprite| sprite.any_variant(rng))
+    }
+}
+
 #[derive(Component)]
 pub struct Target {
     pub path: Option<Vec<MapPosition>>,
@@ -1,5 +1,8 @@
-use crate::asset_manager::TileSheetType;
+use crate::components::attributes::{CombatStats, Energy, Health};
+use crate::components::basic::{Corpse, Faction, Monster, Player, Visible};
 use crate::components::tiles::*;
+use crate::sprite_catalog::{SpriteCatalog, names};
+use crate::systems::pathfinding::{Avoidance, astar::ActorAvoidance};
 use bevy::prelude::*;
 
 #[derive(Bundle)]
@@ -9,39 +12,169 @@ pub struct FloorTileBundle {
     pub map_position: MapPosition,
 }
 
-impl Default for FloorTileBundle {
-    fn default() -> Self {
-        FloorTileBundle {
-            walkable: Walkable::default(),
-            sheetsprite: SheetSprite {
-                tilesheet: TileSheetType::World,
-                tilesheet_x: 5,
-                tilesheet_y: 8,
+#[derive(Bundle)]
+pub struct WallTileBundle {
+    pub blocking: Blocking,
+    pub sheetsprite: SheetSprite,
+    pub map_position: MapPosition,
+    pub layer: Layer,
+}
+
+impl WallTileBundle {
+    pub fn new(catalog: &SpriteCatalog, map_position: MapPosition) -> Self {
+        WallTileBundle {
+            sheetsprite: SheetSprite::named(catalog, names::WALL),
+            blocking: Blocking,
+            layer: Layer(1),
+            map_position,
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct PlayerBundle {
+    pub player: Player,
+    pub target: Target,
+    pub sheetsprite: SheetSprite,
+    pub map_position: MapPosition,
+    pub layer: Layer,
+    pub energy: Energy,
+    pub faction: Faction,
+    pub health: Health,
+    pub combat_stats: CombatStats,
+    pub avoidance: Avoidance,
+}
+
+impl PlayerBundle {
+    pub fn new(catalog: &SpriteCatalog, map_position: MapPosition) -> Self {
+        PlayerBundle {
+            player: Player,
+            target: Target {
+                path: None,
+                position: None,
+            },
+            sheetsprite: SheetSprite::named(catalog, names::PLAYER),
+            map_position,
+            layer: Layer(1),
+            energy: Energy::default(),
+            faction: Faction::Heroes,
+            health: Health::new(30.0),
+            combat_stats: CombatStats {
+                attack: 5.0,
+                defense: 1.0,
             },
-            map_position: MapPosition::default(),
+            // Rather fight through a monster than take a long way round
+            avoidance: Avoidance(ActorAvoidance::Penalty(5)),
         }
     }
 }
 
 #[derive(Bundle)]
-pub struct WallTileBundle {
-    pub blocking: Blocking,
+pub struct MonsterBundle {
+    pub monster: Monster,
     pub sheetsprite: SheetSprite,
     pub map_position: MapPosition,
     pub layer: Layer,
+    pub energy: Energy,
+    pub visible: Visible,
+    pub faction: Faction,
+    pub health: Health,
+    pub combat_stats: CombatStats,
 }
 
-impl Default for WallTileBundle {
-    fn default() -> Self {
-        WallTileBundle {
-            sheetsprite: SheetSprite {
-                tilesheet: TileSheetType::World,
-                tilesheet_x: 16,
-                tilesheet_y: 6,
+/// The kinds of monster map generation places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonsterKind {
+    Goblin,
+    Skeleton,
+    Rat,
+    Bat,
+}
+
+impl MonsterKind {
+    pub const ALL: [MonsterKind; 4] = [
+        MonsterKind::Goblin,
+        MonsterKind::Skeleton,
+        MonsterKind::Rat,
+        MonsterKind::Bat,
+    ];
+
+    /// The kind's own sprite in the catalog.
+    pub fn sprite_name(self) -> &'static str {
+        match self {
+            MonsterKind::Goblin => names::GOBLIN,
+            MonsterKind::Skeleton => names::SKELETON,
+            MonsterKind::Rat => names::RAT,
+            MonsterKind::Bat => names::BAT,
+        }
+    }
+
+    /// Looks a kind up as level files name it, either plainly ("rat") or by
+    /// its catalog sprite ("monster_rat").
+    pub fn from_name(name: &str) -> Option<Self> {
+        MonsterKind::ALL.into_iter().find(|kind| {
+            let sprite = kind.sprite_name();
+            name == sprite || sprite.strip_prefix("monster_") == Some(name)
+        })
+    }
+
+    pub fn bundle(self, catalog: &SpriteCatalog, map_position: MapPosition) -> MonsterBundle {
+        // (health, attack, defense, speed)
+        let (health, attack, defense, speed) = match self {
+            MonsterKind::Goblin => (8.0, 3.0, 0.0, 10),
+            MonsterKind::Skeleton => (12.0, 4.0, 1.0, 8),
+            MonsterKind::Rat => (4.0, 2.0, 0.0, 12),
+            MonsterKind::Bat => (5.0, 2.0, 0.0, 15),
+        };
+        let monster = match self {
+            MonsterKind::Goblin => Monster {
+                flee_below: 0.25,
+                ..default()
             },
-            blocking: Blocking,
+            MonsterKind::Skeleton => Monster {
+                sight_range: 8,
+                wanders: false,
+                ..default()
+            },
+            MonsterKind::Rat => Monster {
+                sight_range: 5,
+                flee_below: 0.5,
+                ..default()
+            },
+            MonsterKind::Bat => Monster::default(),
+        };
+
+        MonsterBundle {
+            monster,
+            sheetsprite: SheetSprite::named(catalog, self.sprite_name()),
+            map_position,
+            layer: Layer(1),
+            energy: Energy { current: 0, speed },
+            visible: Visible::default(),
+            faction: Faction::Monsters,
+            health: Health::new(health),
+            combat_stats: CombatStats { attack, defense },
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct CorpseBundle {
+    pub corpse: Corpse,
+    pub sheetsprite: SheetSprite,
+    pub map_position: MapPosition,
+    pub layer: Layer,
+    pub visible: Visible,
+}
+
+impl CorpseBundle {
+    pub fn new(catalog: &SpriteCatalog, map_position: MapPosition) -> Self {
+        CorpseBundle {
+            corpse: Corpse,
+            sheetsprite: SheetSprite::named(catalog, names::CORPSE),
+            map_position,
             layer: Layer(1),
-            map_position: MapPosition::default(),
+            visible: Visible::default(),
         }
     }
 }
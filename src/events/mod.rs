@@ -1,4 +1,22 @@
 use bevy::prelude::*;
 
-#[derive(Event)]
-pub struct HighlightEvent(pub Entity, pub bool); // true is add, false is remove
+/// An actor swinging at another one, resolved by `resolve_attacks`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub defender: Entity,
+}
+
+/// Outcome of an attack, for the UI and the log.
+#[derive(Event, Debug, Clone, Copy)]
+pub enum CombatEvent {
+    Hit {
+        attacker: Entity,
+        defender: Entity,
+        damage: f32,
+    },
+    Killed {
+        attacker: Entity,
+        defender: Entity,
+    },
+}
@@ -4,16 +4,15 @@ const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.75, 0.35);
 
+type ButtonLook<'a> = (
+    &'a Interaction,
+    &'a mut BackgroundColor,
+    &'a mut BorderColor,
+    &'a Children,
+);
+
 pub fn button_system(
-    mut interaction_query: Query<
-        (
-            &Interaction,
-            &mut BackgroundColor,
-            &mut BorderColor,
-            &Children,
-        ),
-        (Changed<Interaction>, With<Button>),
-    >,
+    mut interaction_query: Query<ButtonLook, (Changed<Interaction>, With<Button>)>,
     mut text_query: Query<&mut Text>,
 ) {
     for (interaction, mut color, mut border_color, children) in &mut interaction_query {
@@ -38,11 +37,33 @@ pub fn button_system(
     }
 }
 
-pub fn setup_game_ui(mut commands: Commands, assets: Res<AssetServer>) {
+pub fn setup_game_ui(mut commands: Commands) {
     // ui camera
     commands.spawn(button());
 }
 
+/// Tells the player they died, over the middle of the screen.
+pub fn show_game_over(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        children![(
+            Text::new("You died"),
+            TextFont {
+                font_size: 48.0,
+                ..default()
+            },
+            TextColor(RED.into()),
+            TextShadow::default(),
+        )],
+    ));
+}
+
 fn button() -> impl Bundle + use<> {
     (
         Node {
@@ -4,85 +4,170 @@ mod components;
 mod entities;
 mod events;
 mod game_ui;
+mod sprite_catalog;
 mod systems;
+mod tile_material;
 use asset_manager::{
-    attach_sprites, setup_asset_manager, slice_tilesheets_into_cache,
+    TileSheets, attach_sprites, build_tile_materials, setup_asset_manager,
     sync_transform_to_map_position,
 };
-use events::HighlightEvent;
-use game_ui::gameui::{button_system, setup_game_ui};
+use events::{AttackEvent, CombatEvent};
+use game_ui::gameui::{button_system, setup_game_ui, show_game_over};
+use sprite_catalog::SpriteCatalog;
 use systems::{
-    game_input::cursor::*,
+    ai::*,
+    camera::*,
+    combat::*,
+    fov::*,
+    game_input::{actions::*, cursor::*},
     pathfinding::*,
-    tile_map::{generation::*, highlight::*},
+    tile_map::{
+        ascii::AsciiLevelLoader,
+        chunks::{TerrainChunks, rebuild_terrain_chunks, track_terrain_chunks},
+        generation::*,
+        grid::sync_tile_map,
+        highlight::*,
+        level::{LevelAsset, PendingLevel},
+        tiled::TiledLevelLoader,
+    },
+    turns::*,
 };
+use tile_material::TileMaterial;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default, States)]
 pub enum AppState {
     #[default]
     AssetLoading,
     Game,
+    /// The player has died; the map stays on screen but nobody acts.
+    GameOver,
 }
 
 fn main() {
     App::new()
         .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: "Duds".into(),
-                    resolution: (1280., 720.).into(),
-                    ..default()
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "Duds".into(),
+                        resolution: (1280., 720.).into(),
+                        ..default()
+                    }),
+                    exit_condition: bevy::window::ExitCondition::OnPrimaryClosed,
+                    close_when_requested: true,
                 }),
-                exit_condition: bevy::window::ExitCondition::OnPrimaryClosed,
-                close_when_requested: true,
-                ..default()
-            }),
             MeshPickingPlugin,
+            MaterialPlugin::<TileMaterial>::default(),
         ))
-        .add_event::<HighlightEvent>()
+        .add_event::<AttackEvent>()
+        .add_event::<CombatEvent>()
+        // The level loaders resolve tileset images and sprite names with these
+        .insert_resource(TileSheets::from_args())
+        .insert_resource(SpriteCatalog::from_args())
+        .init_asset::<LevelAsset>()
+        .init_asset_loader::<AsciiLevelLoader>()
+        .init_asset_loader::<TiledLevelLoader>()
+        .insert_resource(MapSeed::from_args())
+        .init_resource::<AiRng>()
+        .insert_resource(MapGenerator::from_args())
+        .insert_resource(Movement::from_args())
+        .insert_resource(BindingsFile::from_args())
+        .init_resource::<InputBindings>()
+        .init_resource::<ActionState>()
+        .init_resource::<HoveredTile>()
+        .init_resource::<PathPreview>()
+        .init_resource::<PathCache>()
+        .init_resource::<Occupancy>()
+        .init_resource::<PlayerFlowField>()
+        .init_resource::<FovSettings>()
+        .init_resource::<FieldOfView>()
+        .init_resource::<TerrainChunks>()
+        .add_observer(track_hovered_tile)
+        .add_observer(clear_hovered_tile)
         .insert_state::<AppState>(AppState::AssetLoading)
         .add_systems(
             Startup,
-            (setup_asset_manager, test_stuff, spawn_camera, setup_game_ui).chain(),
+            (
+                setup_asset_manager,
+                load_bindings,
+                test_stuff,
+                spawn_camera,
+                setup_game_ui,
+            )
+                .chain(),
+        )
+        .add_systems(OnEnter(AppState::GameOver), show_game_over)
+        .add_systems(
+            PreUpdate,
+            update_action_state.after(bevy::input::InputSystem),
         )
         .add_systems(
             Update,
             (
-                slice_tilesheets_into_cache.run_if(in_state(AppState::AssetLoading)),
-                attach_sprites.run_if(in_state(AppState::Game)),
-                cursor_clicked,
+                build_tile_materials.run_if(in_state(AppState::AssetLoading)),
+                attach_sprites.run_if(not(in_state(AppState::AssetLoading))),
+                spawn_pending_level,
+                update_path_preview.after(find_path),
+                cursor_clicked.after(update_path_preview),
                 highlight_changed,
-                highlight_target_path,
-                find_path,
+                highlight_target_path.after(cursor_clicked),
+                sync_tile_map,
+                update_occupancy,
+                find_path.after(sync_tile_map).after(update_occupancy),
+                (
+                    advance_turns,
+                    player_turn,
+                    move_along_path,
+                    update_flow_field,
+                    monster_turn,
+                    resolve_attacks,
+                    log_combat_events,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Game))
+                    .after(find_path)
+                    .before(update_field_of_view),
+                (
+                    update_field_of_view,
+                    apply_field_of_view,
+                    render_fog_of_war,
+                    track_terrain_chunks.after(highlight_changed),
+                    rebuild_terrain_chunks,
+                )
+                    .chain()
+                    .after(sync_tile_map),
+                interrupt_auto_move.after(apply_field_of_view),
                 button_system,
+                reload_bindings,
+                (
+                    center_camera_on_map,
+                    toggle_camera_mode,
+                    toggle_camera_view,
+                    zoom_camera,
+                    pan_camera,
+                    follow_player,
+                    clamp_camera_to_map,
+                )
+                    .chain(),
             ),
         )
-        .add_systems(
-            FixedUpdate,
-            (
-                move_along_path,
-                sync_transform_to_map_position,
-                camera_movement_system,
-            ),
-        )
+        .add_systems(FixedUpdate, sync_transform_to_map_position)
         .run();
 }
 
-fn test_stuff(mut commands: Commands) {
-    commands.spawn((
-        components::basic::Player,
-        components::tiles::Target {
-            path: None,
-            position: None,
-        },
-        components::tiles::SheetSprite {
-            tilesheet: asset_manager::TileSheetType::Monsters,
-            tilesheet_x: 2,
-            tilesheet_y: 1,
-        },
-        components::tiles::MapPosition { x: 10, y: 10 },
-        components::tiles::Layer(1),
-    ));
+fn test_stuff(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    generator: Res<MapGenerator>,
+    seed: Res<MapSeed>,
+    catalog: Res<SpriteCatalog>,
+) {
+    if let Some(pending) = PendingLevel::from_args(&asset_server) {
+        commands.insert_resource(pending);
+    } else {
+        generate_level(&mut commands, &generator, *seed, &catalog);
+    }
     // ambient light
     commands.insert_resource(AmbientLight {
         color: Color::WHITE,
@@ -99,61 +184,4 @@ fn test_stuff(mut commands: Commands) {
         },
         Transform::from_xyz(16.0, 16.0, 20.0),
     ));
-    generate_test_map(commands);
-}
-fn spawn_camera(mut commands: Commands) {
-    // commands.spawn((
-    //     Name::new("Camera"),
-    //     Camera2d::default(),
-    //     Projection::from(OrthographicProjection {
-    //         scaling_mode: bevy::render::camera::ScalingMode::FixedVertical {
-    //             viewport_height: (500.),
-    //         },
-
-    //         scale: 1.,
-    //         ..OrthographicProjection::default_2d()
-    //     }),
-    //     Camera {
-    //         order: 1,
-    //         ..default()
-    //     },
-    //     Transform::from_xyz(256.0, 240.0, 5.0),
-    // ));
-
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(10.0, 10.0, 30.0).looking_at(Vec3::new(10.0, 10.0, 2.0), Vec3::Y),
-        Camera { ..default() },
-    ));
-}
-
-fn camera_movement_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<&mut Transform, With<Camera>>,
-    time: Res<Time>,
-) {
-    let mut direction = Vec3::ZERO;
-
-    if keyboard_input.pressed(KeyCode::KeyQ) {
-        direction += Vec3::Z;
-    }
-    if keyboard_input.pressed(KeyCode::KeyE) {
-        direction -= Vec3::Z;
-    }
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        direction += Vec3::Y;
-    }
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        direction -= Vec3::Y;
-    }
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        direction -= Vec3::X;
-    }
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        direction += Vec3::X;
-    }
-
-    for mut transform in query.iter_mut() {
-        transform.translation += direction * time.delta_secs() * 10.0;
-    }
 }
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use rand::{Rng, seq::IndexedRandom};
+use serde::Deserialize;
+
+use crate::{
+    asset_manager::{SheetId, asset_file},
+    components::tiles::SheetSprite,
+};
+
+// Relative to the asset folder
+const DEFAULT_CATALOG_PATH: &str = "tilesheets/sprites.ron";
+
+/// Sprites the game itself asks for. Content files can use any name in the
+/// catalog.
+pub mod names {
+    pub const FLOOR: &str = "floor_dirt";
+    pub const WALL: &str = "wall_stone";
+    pub const DOOR: &str = "door_wooden";
+    pub const PLAYER: &str = "hero";
+    pub const CORPSE: &str = "corpse";
+    pub const GOBLIN: &str = "monster_goblin";
+    pub const SKELETON: &str = "monster_skeleton";
+    pub const RAT: &str = "monster_rat";
+    pub const BAT: &str = "monster_bat";
+
+    pub const ALL: [&str; 9] = [
+        FLOOR, WALL, DOOR, PLAYER, CORPSE, GOBLIN, SKELETON, RAT, BAT,
+    ];
+}
+
+/// One entry of the sprite catalog. Coordinates count like `SheetSprite`'s,
+/// `x` being the row and `y` the column.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpriteDefinition {
+    pub sheet: SheetId,
+    pub x: u32,
+    pub y: u32,
+    /// Other cells, as `(x, y)`, that can stand in for this one to break up
+    /// repeated tiles.
+    #[serde(default)]
+    pub variants: Vec<(u32, u32)>,
+}
+
+impl SpriteDefinition {
+    fn at(&self, (x, y): (u32, u32)) -> SheetSprite {
+        SheetSprite {
+            tilesheet: self.sheet.clone(),
+            tilesheet_x: x,
+            tilesheet_y: y,
+        }
+    }
+
+    pub fn sprite(&self) -> SheetSprite {
+        self.at((self.x, self.y))
+    }
+
+    /// The sprite or one of its variants, picked at random.
+    pub fn any_variant(&self, rng: &mut impl Rng) -> SheetSprite {
+        let base = [(self.x, self.y)];
+        let cells: Vec<_> = base.iter().chain(&self.variants).copied().collect();
+        self.at(*cells.choose(rng).unwrap())
+    }
+}
+
+/// Sprites by name, so content refers to "wall_stone" rather than to where
+/// the wall happens to sit in a sheet.
+#[derive(Resource, Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct SpriteCatalog(pub HashMap<String, SpriteDefinition>);
+
+impl SpriteCatalog {
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// Reads the catalog at `path` and reports the sprites the game needs but
+    /// can't find. Those are left undrawn.
+    pub fn load(path: &Path) -> Self {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| SpriteCatalog::parse(&source).map_err(|err| err.to_string()));
+        let catalog = parsed.unwrap_or_else(|err| {
+            error!("Can't read the sprite catalog {}: {err}", path.display());
+            SpriteCatalog::default()
+        });
+        for name in names::ALL {
+            if catalog.get(name).is_none() {
+                error!("Sprite catalog {} has no '{name}'", path.display());
+            }
+        }
+        catalog
+    }
+
+    /// Loads the catalog given with `--sprites`, or the shipped one.
+    pub fn from_args() -> Self {
+        let path = crate::systems::tile_map::generation::arg_value("--sprites")
+            .map_or_else(|| asset_file(DEFAULT_CATALOG_PATH), PathBuf::from);
+        SpriteCatalog::load(&path)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SpriteDefinition> {
+        self.0.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_manager::TileSheets;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn shipped() -> SpriteCatalog {
+        SpriteCatalog::parse(include_str!("../assets/tilesheets/sprites.ron")).unwrap()
+    }
+
+    #[test]
+    fn shipped_catalog_has_every_sprite_the_game_uses() {
+        let catalog = shipped();
+        let sheets = TileSheets::parse(include_str!("../assets/tilesheets/sheets.ron")).unwrap();
+        for name in names::ALL {
+            let sprite = catalog.get(name).unwrap_or_else(|| panic!("no {name}"));
+            assert!(sheets.0.contains_key(&sprite.sheet), "{name}'s sheet");
+        }
+    }
+
+    #[test]
+    fn variants_are_picked_along_with_the_sprite() {
+        let catalog = SpriteCatalog::parse(
+            r#"{ "floor": (sheet: "world", x: 5, y: 8, variants: [(5, 9)]) }"#,
+        )
+        .unwrap();
+        let floor = catalog.get("floor").unwrap();
+        assert_eq!(SheetSprite::named(&catalog, "floor"), floor.sprite());
+        let mut rng = StdRng::seed_from_u64(3);
+        let picked: Vec<u32> = (0..20)
+            .map(|_| floor.any_variant(&mut rng).tilesheet_y)
+            .collect();
+        assert!(picked.contains(&8) && picked.contains(&9));
+        assert_eq!(
+            SheetSprite::named(&catalog, "ceiling"),
+            SheetSprite::MISSING
+        );
+    }
+}
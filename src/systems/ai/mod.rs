@@ -0,0 +1,131 @@
+use crate::components::{
+    attributes::{Energy, Health},
+    basic::{AiState, Monster, Player, TakingTurn, Visible},
+    tiles::MapPosition,
+};
+use crate::events::AttackEvent;
+use crate::systems::{
+    pathfinding::{Movement, PlayerFlowField, astar::manhattan_distance},
+    tile_map::{generation::MapSeed, grid::TileMap},
+    turns::spend_turn,
+};
+use bevy::prelude::*;
+use rand::seq::IndexedRandom;
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashSet;
+
+/// Randomness for monster decisions. It comes from the map seed, on its own
+/// stream so it doesn't shift the layout, and a seed replays whole runs.
+#[derive(Resource)]
+pub struct AiRng(pub ChaCha8Rng);
+
+impl FromWorld for AiRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<MapSeed>()
+            .copied()
+            .unwrap_or(MapSeed(0));
+        let mut rng = seed.rng();
+        rng.set_stream(1);
+        AiRng(rng)
+    }
+}
+
+type ActingMonster<'a> = (
+    Entity,
+    &'a mut Monster,
+    &'a mut MapPosition,
+    &'a mut Energy,
+    &'a Health,
+    &'a Visible,
+    Option<&'a Movement>,
+);
+
+/// Picks a state for every monster whose turn it is and acts on it: chasers
+/// roll downhill on the player's flow field and attack once adjacent, fleeing
+/// monsters roll uphill, and wanderers shuffle around at random.
+#[allow(clippy::too_many_arguments)]
+pub fn monster_turn(
+    mut commands: Commands,
+    tile_map: Option<Res<TileMap>>,
+    map_rules: Res<Movement>,
+    flow_field: Res<PlayerFlowField>,
+    mut monsters: Query<ActingMonster, (With<TakingTurn>, Without<Player>)>,
+    waiting: Query<&MapPosition, (With<Monster>, Without<TakingTurn>)>,
+    player: Query<(Entity, &MapPosition), With<Player>>,
+    mut attacks: EventWriter<AttackEvent>,
+    mut rng: ResMut<AiRng>,
+) {
+    let Some(tile_map) = tile_map else {
+        return;
+    };
+    let player = player.single().ok();
+    let mut occupied: HashSet<MapPosition> = waiting
+        .iter()
+        .chain(monsters.iter().map(|(_, _, pos, ..)| pos))
+        .chain(player.map(|(_, pos)| pos))
+        .copied()
+        .collect();
+
+    for (entity, mut monster, mut map_pos, mut energy, health, visible, rules) in
+        monsters.iter_mut()
+    {
+        let rules = rules.unwrap_or(&map_rules);
+        // Sight is symmetric, so a monster sees the player exactly when the
+        // player sees it
+        let target = player.filter(|(_, player_pos)| {
+            *visible == Visible::InView
+                && manhattan_distance(*map_pos, **player_pos) <= monster.sight_range
+        });
+
+        monster.state = match target {
+            Some(_) if health.current < health.max * monster.flee_below => AiState::Flee,
+            Some(_) => AiState::Chase,
+            None if monster.wanders => AiState::Wander,
+            None => AiState::Idle,
+        };
+
+        let free = |pos: &MapPosition, occupied: &HashSet<MapPosition>| {
+            tile_map.walk_cost(*pos).is_some() && !occupied.contains(pos)
+        };
+        let step = match (monster.state, target) {
+            (AiState::Chase, Some((player_entity, player_pos))) => {
+                if rules
+                    .walkable_steps(&*tile_map, *map_pos)
+                    .contains(player_pos)
+                {
+                    attacks.write(AttackEvent {
+                        attacker: entity,
+                        defender: player_entity,
+                    });
+                    None
+                } else {
+                    let steps = rules.walkable_steps(&*tile_map, *map_pos);
+                    let free_steps = steps.into_iter().filter(|next| free(next, &occupied));
+                    flow_field.0.downhill(free_steps, *map_pos)
+                }
+            }
+            (AiState::Flee, Some(_)) => {
+                let steps = rules.walkable_steps(&*tile_map, *map_pos);
+                let free_steps = steps.into_iter().filter(|next| free(next, &occupied));
+                flow_field.0.uphill(free_steps, *map_pos)
+            }
+            (AiState::Wander, _) => {
+                let options: Vec<MapPosition> = rules
+                    .walkable_steps(&*tile_map, *map_pos)
+                    .into_iter()
+                    .filter(|next| free(next, &occupied))
+                    .collect();
+                options.choose(&mut rng.0).copied()
+            }
+            _ => None,
+        };
+
+        if let Some(next) = step {
+            occupied.remove(&map_pos);
+            occupied.insert(next);
+            *map_pos = next;
+        }
+        spend_turn(&mut commands, entity, &mut energy);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::components::basic::Player;
+use crate::systems::{
+    game_input::actions::{Action, ActionState},
+    tile_map::{grid::MapSize, util::TILE_SIZE},
+};
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::window::PrimaryWindow;
+
+/// Whether the camera sticks to the player or stays where it was put.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraMode {
+    #[default]
+    Follow,
+    Free,
+}
+
+/// How the map is projected onto the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraView {
+    /// Looking down at the map with perspective. Zooming changes the height.
+    #[default]
+    Perspective,
+    /// Straight top-down with every tile texel drawn as a whole number of
+    /// screen pixels. Zooming changes that number.
+    Orthographic,
+}
+
+/// Drives the game camera. Its height above the map is the zoom level.
+#[derive(Component, Debug, Clone)]
+pub struct CameraController {
+    pub mode: CameraMode,
+    pub view: CameraView,
+    pub min_height: f32,
+    pub max_height: f32,
+    /// Height change per second while a zoom action is held.
+    pub zoom_speed: f32,
+    /// Height change per line of mouse wheel scroll.
+    pub scroll_step: f32,
+    /// Tiles per second when panning with keys or the cursor at a screen edge.
+    pub pan_speed: f32,
+    /// Width in pixels of the screen border that pans the camera.
+    pub edge_margin: f32,
+    /// How quickly the camera catches up with the player, per second.
+    pub follow_rate: f32,
+    /// Screen pixels per tile texel in the orthographic view.
+    pub pixel_scale: u32,
+    pub max_pixel_scale: u32,
+    /// Wheel lines scrolled towards the next pixel scale step. Trackpads
+    /// scroll a fraction of a line at a time.
+    scrolled: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            mode: CameraMode::Follow,
+            view: CameraView::Perspective,
+            min_height: 6.0,
+            max_height: 60.0,
+            zoom_speed: 20.0,
+            scroll_step: 2.0,
+            pan_speed: 10.0,
+            edge_margin: 16.0,
+            follow_rate: 5.0,
+            pixel_scale: 3,
+            max_pixel_scale: 8,
+            scrolled: 0.0,
+        }
+    }
+}
+
+pub fn spawn_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 0.0, 30.0).looking_to(Vec3::NEG_Z, Vec3::Y),
+        CameraController::default(),
+    ));
+}
+
+/// Puts the camera over the middle of each new map. Loaded levels only get
+/// their size once the file has been read, some frames after startup.
+pub fn center_camera_on_map(
+    map_size: Option<Res<MapSize>>,
+    mut cameras: Query<&mut Transform, With<CameraController>>,
+) {
+    let Some(map_size) = map_size.filter(|size| size.is_changed()) else {
+        return;
+    };
+    let center = map_size.center();
+    for mut transform in cameras.iter_mut() {
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
+
+impl CameraController {
+    /// Screen pixels per map tile in the orthographic view.
+    fn pixels_per_tile(&self) -> f32 {
+        (TILE_SIZE as u32 * self.pixel_scale) as f32
+    }
+
+    fn projection(&self) -> Projection {
+        match self.view {
+            CameraView::Perspective => Projection::Perspective(default()),
+            CameraView::Orthographic => Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::WindowSize,
+                scale: 1.0 / self.pixels_per_tile(),
+                ..OrthographicProjection::default_3d()
+            }),
+        }
+    }
+}
+
+// -1, 0 or 1 depending on which of two opposing inputs are held.
+fn axis(positive: bool, negative: bool) -> f32 {
+    (positive as i8 - negative as i8) as f32
+}
+
+/// Half the width and height of the map area in view at the camera's height.
+fn visible_half_extents(projection: &Projection, height: f32, window: Option<&Window>) -> Vec2 {
+    let aspect = window.map_or(16.0 / 9.0, |window| window.width() / window.height());
+    let fov = match projection {
+        Projection::Orthographic(orthographic) => {
+            return window.map_or(orthographic.area.half_size(), |window| {
+                window.size() * orthographic.scale / 2.0
+            });
+        }
+        Projection::Perspective(perspective) => perspective.fov,
+        _ => PerspectiveProjection::default().fov,
+    };
+    let half_height = height * (fov / 2.0).tan();
+    Vec2::new(half_height * aspect, half_height)
+}
+
+/// Adds `lines` of scrolling to `scrolled` and takes out the whole lines, as
+/// steps. Turning back drops what was scrolled the other way.
+fn scroll_steps(scrolled: &mut f32, lines: f32) -> i32 {
+    if lines * *scrolled < 0.0 {
+        *scrolled = 0.0;
+    }
+    *scrolled += lines;
+    let steps = scrolled.trunc();
+    *scrolled -= steps;
+    steps as i32
+}
+
+/// Rounds `value` to the nearest multiple of `1 / per_unit`.
+fn snap(value: f32, per_unit: f32) -> f32 {
+    (value * per_unit).round() / per_unit
+}
+
+/// Keeps a view of `2 * half_extent` inside `min..=max`, or centers it when
+/// the view is wider than that range.
+fn clamp_to_bounds(center: f32, half_extent: f32, min: f32, max: f32) -> f32 {
+    if max - min <= 2.0 * half_extent {
+        (min + max) / 2.0
+    } else {
+        center.clamp(min + half_extent, max - half_extent)
+    }
+}
+
+pub fn toggle_camera_mode(actions: Res<ActionState>, mut cameras: Query<&mut CameraController>) {
+    if !actions.just_pressed(Action::CameraToggleFollow) {
+        return;
+    }
+    for mut controller in cameras.iter_mut() {
+        controller.mode = match controller.mode {
+            CameraMode::Follow => CameraMode::Free,
+            CameraMode::Free => CameraMode::Follow,
+        };
+    }
+}
+
+/// Swaps between the perspective and the pixel-perfect orthographic view.
+pub fn toggle_camera_view(
+    actions: Res<ActionState>,
+    mut cameras: Query<(&mut CameraController, &mut Projection)>,
+) {
+    if !actions.just_pressed(Action::CameraToggleView) {
+        return;
+    }
+    for (mut controller, mut projection) in cameras.iter_mut() {
+        controller.view = match controller.view {
+            CameraView::Perspective => CameraView::Orthographic,
+            CameraView::Orthographic => CameraView::Perspective,
+        };
+        *projection = controller.projection();
+    }
+}
+
+/// Zooms by changing the camera's height in perspective, or by whole pixel
+/// scale steps in the orthographic view.
+pub fn zoom_camera(
+    actions: Res<ActionState>,
+    scroll: Res<AccumulatedMouseScroll>,
+    time: Res<Time>,
+    mut cameras: Query<(&mut Transform, &mut CameraController, &mut Projection)>,
+) {
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / 16.0,
+    };
+    let held = axis(
+        actions.pressed(Action::CameraZoomOut),
+        actions.pressed(Action::CameraZoomIn),
+    );
+
+    for (mut transform, mut controller, mut projection) in cameras.iter_mut() {
+        if controller.view == CameraView::Orthographic {
+            let steps = scroll_steps(&mut controller.scrolled, lines)
+                + actions.just_pressed(Action::CameraZoomIn) as i32
+                - actions.just_pressed(Action::CameraZoomOut) as i32;
+            let scale = controller
+                .pixel_scale
+                .saturating_add_signed(steps)
+                .clamp(1, controller.max_pixel_scale);
+            if scale != controller.pixel_scale {
+                controller.pixel_scale = scale;
+                *projection = controller.projection();
+            }
+            continue;
+        }
+        let change =
+            held * controller.zoom_speed * time.delta_secs() - lines * controller.scroll_step;
+        transform.translation.z =
+            (transform.translation.z + change).clamp(controller.min_height, controller.max_height);
+    }
+}
+
+/// Which way the cursor resting on a screen edge pans. Edges don't pan while
+/// the window is in the background.
+fn edge_pan(window: &Window, margin: f32) -> Vec2 {
+    let Some(cursor) = window.cursor_position().filter(|_| window.focused) else {
+        return Vec2::ZERO;
+    };
+    let size = window.size();
+    Vec2::new(
+        axis(cursor.x >= size.x - margin, cursor.x <= margin),
+        // Window coordinates grow downwards
+        axis(cursor.y <= margin, cursor.y >= size.y - margin),
+    )
+}
+
+/// Pans with the pan actions, by dragging, or by resting the cursor on a
+/// screen edge. Any of them lets go of the player.
+pub fn pan_camera(
+    actions: Res<ActionState>,
+    motion: Res<AccumulatedMouseMotion>,
+    time: Res<Time>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Transform, &mut CameraController, &Projection)>,
+) {
+    let window = window.single().ok();
+    let mut direction = Vec2::ZERO;
+    for (action, step) in [
+        (Action::CameraPanNorth, Vec2::Y),
+        (Action::CameraPanSouth, Vec2::NEG_Y),
+        (Action::CameraPanEast, Vec2::X),
+        (Action::CameraPanWest, Vec2::NEG_X),
+    ] {
+        if actions.pressed(action) {
+            direction += step;
+        }
+    }
+    let dragging = actions.pressed(Action::CameraDrag) && motion.delta != Vec2::ZERO;
+
+    for (mut transform, mut controller, projection) in cameras.iter_mut() {
+        let pan = direction
+            + window.map_or(Vec2::ZERO, |window| {
+                edge_pan(window, controller.edge_margin)
+            });
+        if pan != Vec2::ZERO || dragging {
+            controller.mode = CameraMode::Free;
+        }
+        if controller.mode == CameraMode::Follow {
+            continue;
+        }
+
+        let mut offset = pan.normalize_or_zero() * controller.pan_speed * time.delta_secs();
+
+        if dragging && let Some(window) = window {
+            let half = visible_half_extents(projection, transform.translation.z, Some(window));
+            let world_per_pixel = 2.0 * half.y / window.height();
+            // Drag the map along with the cursor
+            offset += Vec2::new(-motion.delta.x, motion.delta.y) * world_per_pixel;
+        }
+        transform.translation += offset.extend(0.0);
+    }
+}
+
+/// Eases the camera towards the player while in follow mode.
+pub fn follow_player(
+    time: Res<Time>,
+    player: Query<&Transform, (With<Player>, Without<CameraController>)>,
+    mut cameras: Query<(&mut Transform, &CameraController)>,
+) {
+    let Ok(player) = player.single() else {
+        return;
+    };
+    for (mut transform, controller) in cameras.iter_mut() {
+        if controller.mode != CameraMode::Follow {
+            continue;
+        }
+        let blend = 1.0 - (-controller.follow_rate * time.delta_secs()).exp();
+        let target = player.translation.truncate();
+        let current = transform.translation.truncate();
+        transform.translation = current.lerp(target, blend).extend(transform.translation.z);
+    }
+}
+
+/// Stops the camera from showing more than it has to beyond the map's edges,
+/// and lines it up with the screen pixels in the orthographic view.
+pub fn clamp_camera_to_map(
+    map_size: Option<Res<MapSize>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Transform, &Projection, &CameraController)>,
+) {
+    let Some(map_size) = map_size else {
+        return;
+    };
+    let window = window.single().ok();
+    // Tiles are centered on their map position
+    let max = Vec2::new(map_size.width as f32, map_size.height as f32) - 0.5;
+    for (mut transform, projection, controller) in cameras.iter_mut() {
+        let half = visible_half_extents(projection, transform.translation.z, window);
+        transform.translation.x = clamp_to_bounds(transform.translation.x, half.x, -0.5, max.x);
+        transform.translation.y = clamp_to_bounds(transform.translation.y, half.y, -0.5, max.y);
+        if controller.view == CameraView::Orthographic {
+            let per_unit = controller.pixels_per_tile();
+            transform.translation.x = snap(transform.translation.x, per_unit);
+            transform.translation.y = snap(transform.translation.y, per_unit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_views_stay_inside_the_map() {
+        assert_eq!(clamp_to_bounds(-3.0, 2.0, 0.0, 10.0), 2.0);
+        assert_eq!(clamp_to_bounds(5.0, 2.0, 0.0, 10.0), 5.0);
+        assert_eq!(clamp_to_bounds(12.0, 2.0, 0.0, 10.0), 8.0);
+    }
+
+    #[test]
+    fn large_views_center_on_the_map() {
+        assert_eq!(clamp_to_bounds(0.0, 8.0, 0.0, 10.0), 5.0);
+    }
+
+    #[test]
+    fn only_focused_windows_pan_at_the_edges() {
+        let mut window = Window::default();
+        window.set_cursor_position(Some(Vec2::new(4.0, window.height() / 2.0)));
+        assert_eq!(edge_pan(&window, 16.0), Vec2::NEG_X);
+        window.focused = false;
+        assert_eq!(edge_pan(&window, 16.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn scrolling_steps_once_per_whole_line() {
+        let mut scrolled = 0.0;
+        assert_eq!(scroll_steps(&mut scrolled, 0.0), 0);
+        assert_eq!(scroll_steps(&mut scrolled, 1.0), 1);
+        assert_eq!(scroll_steps(&mut scrolled, -2.0), -2);
+        assert_eq!(scroll_steps(&mut scrolled, 0.0), 0);
+        // Trackpad pixels add up to a line
+        assert_eq!(scroll_steps(&mut scrolled, 0.5), 0);
+        assert_eq!(scroll_steps(&mut scrolled, 0.0), 0);
+        assert_eq!(scroll_steps(&mut scrolled, 0.5), 1);
+        assert_eq!(scroll_steps(&mut scrolled, 0.75), 0);
+        assert_eq!(scroll_steps(&mut scrolled, -0.75), 0);
+        assert_eq!(scroll_steps(&mut scrolled, -0.5), -1);
+    }
+
+    #[test]
+    fn snapping_lands_on_whole_pixels() {
+        let per_unit = 48.0;
+        assert_eq!(snap(1.0 + 10.4 / per_unit, per_unit), 1.0 + 10.0 / per_unit);
+        assert_eq!(snap(-0.5, per_unit), -0.5);
+    }
+}
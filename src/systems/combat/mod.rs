@@ -0,0 +1,149 @@
+use crate::AppState;
+use crate::components::{
+    attributes::{CombatStats, Health},
+    basic::Player,
+    tiles::MapPosition,
+};
+use crate::entities::CorpseBundle;
+use crate::events::{AttackEvent, CombatEvent};
+use crate::sprite_catalog::SpriteCatalog;
+use bevy::prelude::*;
+
+pub fn melee_damage(attacker: &CombatStats, defender: &CombatStats) -> f32 {
+    (attacker.attack - defender.defense).max(1.0)
+}
+
+/// Applies queued attacks. Anything brought down to zero health is replaced
+/// by a corpse, and the game is over once that is the player.
+pub fn resolve_attacks(
+    mut commands: Commands,
+    mut attacks: EventReader<AttackEvent>,
+    stats: Query<&CombatStats>,
+    mut defenders: Query<(&mut Health, &MapPosition, Has<Player>)>,
+    mut combat_events: EventWriter<CombatEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    catalog: Res<SpriteCatalog>,
+) {
+    for &AttackEvent { attacker, defender } in attacks.read() {
+        let (Ok(attacker_stats), Ok(defender_stats)) = (stats.get(attacker), stats.get(defender))
+        else {
+            continue;
+        };
+        let Ok((mut health, position, is_player)) = defenders.get_mut(defender) else {
+            continue;
+        };
+        if health.is_dead() {
+            continue; // Already killed earlier this frame
+        }
+
+        let damage = melee_damage(attacker_stats, defender_stats);
+        health.current -= damage;
+        combat_events.write(CombatEvent::Hit {
+            attacker,
+            defender,
+            damage,
+        });
+
+        if health.is_dead() {
+            combat_events.write(CombatEvent::Killed { attacker, defender });
+            commands.entity(defender).despawn();
+            commands.spawn(CorpseBundle::new(&catalog, *position));
+            if is_player {
+                next_state.set(AppState::GameOver);
+            }
+        }
+    }
+}
+
+pub fn log_combat_events(mut combat_events: EventReader<CombatEvent>, health: Query<&Health>) {
+    for event in combat_events.read() {
+        match event {
+            CombatEvent::Hit {
+                attacker,
+                defender,
+                damage,
+            } => match health.get(*defender) {
+                Ok(health) => info!(
+                    "{attacker} hits {defender} for {damage} ({}/{} left)",
+                    health.current, health.max
+                ),
+                Err(_) => info!("{attacker} hits {defender} for {damage}"),
+            },
+            CombatEvent::Killed { attacker, defender } => info!("{attacker} kills {defender}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::basic::Corpse;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn defense_soaks_damage_down_to_one_point() {
+        let attacker = CombatStats {
+            attack: 5.0,
+            defense: 0.0,
+        };
+        let armored = |defense| CombatStats {
+            attack: 0.0,
+            defense,
+        };
+        assert_eq!(melee_damage(&attacker, &armored(0.0)), 5.0);
+        assert_eq!(melee_damage(&attacker, &armored(2.0)), 3.0);
+        assert_eq!(melee_damage(&attacker, &armored(9.0)), 1.0);
+    }
+
+    fn fight(defender_health: f32, defender_is_player: bool) -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<AttackEvent>>();
+        world.init_resource::<Events<CombatEvent>>();
+        world.init_resource::<NextState<AppState>>();
+        world.init_resource::<SpriteCatalog>();
+        let stats = CombatStats {
+            attack: 4.0,
+            defense: 1.0,
+        };
+        let attacker = world.spawn(stats).id();
+        let mut defender = world.spawn((
+            stats,
+            Health::new(defender_health),
+            MapPosition { x: 2, y: 3 },
+        ));
+        if defender_is_player {
+            defender.insert(Player);
+        }
+        let defender = defender.id();
+        world.send_event(AttackEvent { attacker, defender });
+        world.run_system_once(resolve_attacks).unwrap();
+        (world, defender)
+    }
+
+    #[test]
+    fn hits_take_health_off_the_defender() {
+        let (world, defender) = fight(10.0, false);
+        assert_eq!(world.get::<Health>(defender).unwrap().current, 7.0);
+    }
+
+    #[test]
+    fn killed_actors_leave_a_corpse() {
+        let (mut world, defender) = fight(3.0, false);
+        assert!(world.get_entity(defender).is_err());
+        let mut corpses = world.query_filtered::<&MapPosition, With<Corpse>>();
+        assert_eq!(corpses.single(&world).unwrap(), &MapPosition { x: 2, y: 3 });
+        assert!(matches!(
+            world.resource::<NextState<AppState>>(),
+            NextState::Unchanged
+        ));
+    }
+
+    #[test]
+    fn killing_the_player_ends_the_game() {
+        let (world, _) = fight(3.0, true);
+        assert!(matches!(
+            world.resource::<NextState<AppState>>(),
+            NextState::Pending(AppState::GameOver)
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::components::{
+    basic::{Player, Visible},
+    tiles::{Blocking, MapPosition, Walkable},
+};
+use crate::systems::tile_map::grid::{MapSize, TileMap};
+use bevy::prelude::*;
+
+#[derive(Resource)]
+pub struct FovSettings {
+    pub radius: u32,
+}
+
+impl Default for FovSettings {
+    fn default() -> Self {
+        FovSettings { radius: 8 }
+    }
+}
+
+/// What the player can currently see and what they have seen before, per tile.
+#[derive(Resource, Default)]
+pub struct FieldOfView {
+    size: Option<MapSize>,
+    states: Vec<Visible>,
+}
+
+impl FieldOfView {
+    pub fn state(&self, pos: MapPosition) -> Visible {
+        match self.size {
+            Some(size) if size.contains(pos) => self.states[pos.y * size.width + pos.x],
+            _ => Visible::Unseen,
+        }
+    }
+}
+
+// Symmetric shadowcasting, after https://www.albertford.com/shadowcasting/
+// Slopes are kept as exact fractions so results don't depend on rounding.
+
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i64,
+    den: i64,
+}
+
+#[derive(Clone, Copy)]
+struct Row {
+    depth: i64,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    fn columns(&self) -> std::ops::RangeInclusive<i64> {
+        // round half up for the start, half down for the end
+        let min = (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den);
+        let max = -((-2 * self.depth * self.end.num + self.end.den).div_euclid(2 * self.end.den));
+        min..=max
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+
+    fn is_symmetric(&self, col: i64) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+fn slope(depth: i64, col: i64) -> Slope {
+    Slope {
+        num: 2 * col - 1,
+        den: 2 * depth,
+    }
+}
+
+struct Quadrant {
+    origin: (i64, i64),
+    cardinal: u8,
+}
+
+impl Quadrant {
+    fn transform(&self, depth: i64, col: i64) -> (i64, i64) {
+        let (x, y) = self.origin;
+        match self.cardinal {
+            0 => (x + col, y + depth),
+            1 => (x + depth, y + col),
+            2 => (x + col, y - depth),
+            _ => (x - depth, y + col),
+        }
+    }
+}
+
+/// Calls `reveal` for every tile visible from `origin` within `radius`.
+pub fn compute_fov(
+    origin: MapPosition,
+    radius: u32,
+    is_opaque: impl Fn(MapPosition) -> bool,
+    mut reveal: impl FnMut(MapPosition),
+) {
+    reveal(origin);
+    let radius = radius as i64;
+    let to_position = |(x, y): (i64, i64)| {
+        (x >= 0 && y >= 0).then_some(MapPosition {
+            x: x as usize,
+            y: y as usize,
+        })
+    };
+
+    for cardinal in 0..4 {
+        let quadrant = Quadrant {
+            origin: (origin.x as i64, origin.y as i64),
+            cardinal,
+        };
+        // Off-map tiles count as walls
+        let is_wall =
+            |depth, col| to_position(quadrant.transform(depth, col)).is_none_or(&is_opaque);
+
+        let mut rows = vec![Row {
+            depth: 1,
+            start: Slope { num: -1, den: 1 },
+            end: Slope { num: 1, den: 1 },
+        }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > radius {
+                continue;
+            }
+            let mut prev_wall: Option<bool> = None;
+            for col in row.columns() {
+                let wall = is_wall(row.depth, col);
+                let in_radius = row.depth * row.depth + col * col <= radius * radius + radius;
+                if in_radius
+                    && (wall || row.is_symmetric(col))
+                    && let Some(pos) = to_position(quadrant.transform(row.depth, col))
+                {
+                    reveal(pos);
+                }
+                if prev_wall == Some(true) && !wall {
+                    row.start = slope(row.depth, col);
+                }
+                if prev_wall == Some(false) && wall {
+                    let mut next = row.next();
+                    next.end = slope(row.depth, col);
+                    rows.push(next);
+                }
+                prev_wall = Some(wall);
+            }
+            if prev_wall == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+}
+
+pub fn update_field_of_view(
+    settings: Res<FovSettings>,
+    tile_map: Option<Res<TileMap>>,
+    mut fov: ResMut<FieldOfView>,
+    player: Query<Ref<MapPosition>, With<Player>>,
+) {
+    let (Some(tile_map), Ok(player_pos)) = (tile_map, player.single()) else {
+        return;
+    };
+    let size = tile_map.size();
+    let new_map = fov.size != Some(size);
+    if !new_map && !player_pos.is_changed() && !tile_map.is_changed() && !settings.is_changed() {
+        return;
+    }
+
+    let fov = &mut *fov;
+    if new_map {
+        fov.size = Some(size);
+        fov.states = vec![Visible::Unseen; size.width * size.height];
+    }
+    for state in fov.states.iter_mut() {
+        if *state == Visible::InView {
+            *state = Visible::Remembered;
+        }
+    }
+    let states = &mut fov.states;
+    compute_fov(
+        *player_pos,
+        settings.radius,
+        |pos| tile_map.is_opaque(pos),
+        |pos| {
+            if let Some(i) = tile_map.index(pos) {
+                states[i] = Visible::InView;
+            }
+        },
+    );
+}
+
+type VisibilityOutdated = Or<(Changed<MapPosition>, Added<Visible>)>;
+type FogSprite<'a> = (Entity, &'a Visible, Has<Walkable>, Has<Blocking>);
+type FogOutdated = Or<(Changed<Visible>, Added<Mesh3d>)>;
+
+pub fn apply_field_of_view(
+    fov: Res<FieldOfView>,
+    mut all: Query<(&MapPosition, &mut Visible)>,
+    mut changed: Query<(&MapPosition, &mut Visible), VisibilityOutdated>,
+) {
+    let apply = |(pos, mut visible): (&MapPosition, Mut<Visible>)| {
+        visible.set_if_neq(fov.state(*pos));
+    };
+    if fov.is_changed() {
+        all.iter_mut().for_each(apply);
+    } else {
+        changed.iter_mut().for_each(apply);
+    }
+}
+
+/// Whether something is drawn under the fog of war: terrain stays on screen
+/// once seen, everything else only while in view.
+pub fn is_shown(visible: Visible, terrain: bool) -> bool {
+    match visible {
+        Visible::InView => true,
+        Visible::Remembered => terrain,
+        Visible::Unseen => false,
+    }
+}
+
+/// Hides unseen sprites and only shows actors while they are in view. Terrain
+/// is drawn, and dimmed, by its chunk.
+pub fn render_fog_of_war(mut commands: Commands, query: Query<FogSprite, FogOutdated>) {
+    for (entity, visible, walkable, blocking) in query.iter() {
+        let shown = is_shown(*visible, walkable || blocking);
+        commands.entity(entity).insert(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn visible_from(origin: MapPosition, walls: &[MapPosition]) -> HashSet<MapPosition> {
+        let mut seen = HashSet::new();
+        compute_fov(
+            origin,
+            10,
+            |pos| pos.x >= 20 || pos.y >= 20 || walls.contains(&pos),
+            |pos| {
+                seen.insert(pos);
+            },
+        );
+        seen
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them() {
+        let wall = MapPosition { x: 5, y: 6 };
+        let seen = visible_from(MapPosition { x: 5, y: 5 }, &[wall]);
+        assert!(seen.contains(&wall));
+        assert!(!seen.contains(&MapPosition { x: 5, y: 7 }));
+        assert!(seen.contains(&MapPosition { x: 5, y: 4 }));
+    }
+
+    #[test]
+    fn sight_is_symmetric() {
+        let walls = [
+            MapPosition { x: 4, y: 6 },
+            MapPosition { x: 7, y: 3 },
+            MapPosition { x: 9, y: 8 },
+        ];
+        let origin = MapPosition { x: 5, y: 5 };
+        for target in visible_from(origin, &walls) {
+            if !walls.contains(&target) {
+                assert!(visible_from(target, &walls).contains(&origin));
+            }
+        }
+    }
+
+    #[test]
+    fn radius_limits_sight() {
+        let seen = visible_from(MapPosition { x: 0, y: 0 }, &[]);
+        assert!(seen.contains(&MapPosition { x: 10, y: 0 }));
+        assert!(!seen.contains(&MapPosition { x: 11, y: 0 }));
+        assert!(!seen.contains(&MapPosition { x: 9, y: 9 }));
+    }
+}
@@ -0,0 +1,249 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::asset_manager::game_file;
+
+/// Where the bindings are read from unless `--bindings <path>` says otherwise,
+/// relative to the folder holding the assets.
+pub const DEFAULT_BINDINGS_PATH: &str = "config/input.ron";
+
+const DEFAULT_BINDINGS: &str = include_str!("../../../config/input.ron");
+
+/// How far a stick has to be pushed before it counts as pressed.
+const STICK_DEADZONE: f32 = 0.5;
+
+/// Everything the player can ask the game to do, independent of the device
+/// that asked for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveNorth,
+    MoveSouth,
+    MoveEast,
+    MoveWest,
+    MoveNorthEast,
+    MoveNorthWest,
+    MoveSouthEast,
+    MoveSouthWest,
+    Wait,
+    /// Walk the path previewed under the cursor.
+    Travel,
+    Cancel,
+    // Bindable ahead of the item systems that will handle them
+    PickUp,
+    OpenInventory,
+    CameraPanNorth,
+    CameraPanSouth,
+    CameraPanEast,
+    CameraPanWest,
+    CameraZoomIn,
+    CameraZoomOut,
+    /// Switch between following the player and panning freely.
+    CameraToggleFollow,
+    /// Held while dragging the map around with the mouse.
+    CameraDrag,
+    /// Switch between the perspective and the pixel-perfect top-down view.
+    CameraToggleView,
+}
+
+impl Action {
+    /// The eight moves and the map step each of them takes.
+    pub const MOVES: [(Action, (isize, isize)); 8] = [
+        (Action::MoveNorth, (0, 1)),
+        (Action::MoveSouth, (0, -1)),
+        (Action::MoveEast, (1, 0)),
+        (Action::MoveWest, (-1, 0)),
+        (Action::MoveNorthEast, (1, 1)),
+        (Action::MoveNorthWest, (-1, 1)),
+        (Action::MoveSouthEast, (1, -1)),
+        (Action::MoveSouthWest, (-1, -1)),
+    ];
+}
+
+/// A single physical input an action can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+    /// A stick or trigger pushed past the deadzone in one direction.
+    GamepadAxis {
+        axis: GamepadAxis,
+        positive: bool,
+    },
+}
+
+/// Maps every action to the inputs that trigger it. Read from a RON file the
+/// player can edit, and re-read whenever that file changes.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputBindings(pub HashMap<Action, Vec<InputBinding>>);
+
+impl Default for InputBindings {
+    /// The bindings shipped in `config/input.ron`, which is the one place they
+    /// are written down.
+    fn default() -> Self {
+        InputBindings::parse(DEFAULT_BINDINGS).expect("the shipped input bindings are valid")
+    }
+}
+
+impl InputBindings {
+    pub fn parse(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// Reads the bindings at `path`, falling back to the defaults when the file
+    /// is missing or broken.
+    pub fn load(path: &Path) -> Self {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                info!("Using default input bindings, {}: {err}", path.display());
+                return InputBindings::default();
+            }
+        };
+        InputBindings::parse(&source).unwrap_or_else(|err| {
+            error!("Invalid input bindings in {}: {err}", path.display());
+            InputBindings::default()
+        })
+    }
+}
+
+/// The bindings file and when it was last read, so edits to it take effect
+/// while the game is running.
+#[derive(Resource)]
+pub struct BindingsFile {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+    poll: Timer,
+}
+
+impl BindingsFile {
+    pub fn from_args() -> Self {
+        let path = crate::systems::tile_map::generation::arg_value("--bindings")
+            .map_or_else(|| game_file(DEFAULT_BINDINGS_PATH), PathBuf::from);
+        BindingsFile {
+            path,
+            modified: None,
+            poll: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+}
+
+pub fn load_bindings(mut file: ResMut<BindingsFile>, mut bindings: ResMut<InputBindings>) {
+    file.modified = file.modified();
+    *bindings = InputBindings::load(&file.path);
+}
+
+/// Re-reads the bindings file whenever it has been saved since it was last
+/// read.
+pub fn reload_bindings(
+    time: Res<Time>,
+    mut file: ResMut<BindingsFile>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    if !file.poll.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = file.modified();
+    if modified.is_some() && modified != file.modified {
+        file.modified = modified;
+        bindings.set_if_neq(InputBindings::load(&file.path));
+        info!("Reloaded input bindings from {}", file.path.display());
+    }
+}
+
+/// Which actions are held and which were triggered this frame, gathered from
+/// every device.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+pub fn update_action_state(
+    bindings: Res<InputBindings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut state: ResMut<ActionState>,
+) {
+    let held = |binding: &InputBinding| match *binding {
+        InputBinding::Key(key) => keyboard.pressed(key),
+        InputBinding::Mouse(button) => mouse.pressed(button),
+        InputBinding::Gamepad(button) => gamepads.iter().any(|pad| pad.pressed(button)),
+        InputBinding::GamepadAxis { axis, positive } => gamepads.iter().any(|pad| {
+            let value = pad.get(axis).unwrap_or_default();
+            if positive {
+                value > STICK_DEADZONE
+            } else {
+                value < -STICK_DEADZONE
+            }
+        }),
+    };
+
+    let mut pressed = HashSet::new();
+    for (action, inputs) in bindings.0.iter() {
+        if inputs.iter().any(held) {
+            pressed.insert(*action);
+        }
+    }
+    state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+    state.pressed = pressed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_the_shipped_bindings() {
+        let bindings = InputBindings::default();
+        assert_eq!(
+            bindings.0.get(&Action::Travel),
+            Some(&vec![InputBinding::Mouse(MouseButton::Left)])
+        );
+        let moves = Action::MOVES.map(|(action, _)| action);
+        for action in moves
+            .into_iter()
+            .chain([Action::PickUp, Action::OpenInventory])
+        {
+            assert!(bindings.0.contains_key(&action), "{action:?} is unbound");
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        let bindings = InputBindings::default();
+        let source = ron::to_string(&bindings).unwrap();
+        assert_eq!(InputBindings::parse(&source), Ok(bindings));
+    }
+
+    #[test]
+    fn partial_files_only_bind_what_they_list() {
+        let bindings = InputBindings::parse("{ Wait: [Key(Space)] }").unwrap();
+        assert_eq!(
+            bindings.0.get(&Action::Wait),
+            Some(&vec![InputBinding::Key(KeyCode::Space)])
+        );
+        assert_eq!(bindings.0.get(&Action::MoveNorth), None);
+    }
+}
@@ -1,26 +1,101 @@
 use bevy::prelude::*;
 
-use crate::components::{attributes::Moving, basic::Player};
+use super::actions::{Action, ActionState};
 
+use crate::components::{
+    attributes::Moving,
+    basic::Player,
+    tiles::{MapPosition, Target},
+};
+use crate::systems::{
+    pathfinding::{Avoidance, Movement, Occupancy, PathCache},
+    tile_map::grid::TileMap,
+};
+
+/// The map tile under the mouse cursor, if any.
+#[derive(Resource, Default, PartialEq)]
+pub struct HoveredTile(pub Option<MapPosition>);
+
+/// Where the player would walk if they clicked right now.
+#[derive(Resource, Default, PartialEq)]
+pub struct PathPreview(pub Option<Vec<MapPosition>>);
+
+/// Tracks the hovered tile from anything on the map: floor, walls or the
+/// actors standing on them.
+pub fn track_hovered_tile(
+    over: Trigger<Pointer<Over>>,
+    positions: Query<&MapPosition>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    if let Ok(pos) = positions.get(over.target()) {
+        hovered.0 = Some(*pos);
+    }
+}
+
+pub fn clear_hovered_tile(
+    out: Trigger<Pointer<Out>>,
+    positions: Query<&MapPosition>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    if positions.get(out.target()).ok().copied() == hovered.0 {
+        hovered.0 = None;
+    }
+}
+
+type PreviewSeeker<'a> = (&'a MapPosition, Option<&'a Movement>, Option<&'a Avoidance>);
+
+pub fn update_path_preview(
+    hovered: Res<HoveredTile>,
+    tile_map: Option<Res<TileMap>>,
+    map_rules: Res<Movement>,
+    occupancy: Res<Occupancy>,
+    mut cache: ResMut<PathCache>,
+    player: Query<PreviewSeeker, With<Player>>,
+    mut preview: ResMut<PathPreview>,
+) {
+    let path = match (tile_map, hovered.0, player.single()) {
+        (Some(tile_map), Some(goal), Ok((start, rules, avoidance))) => {
+            let rules = **rules.unwrap_or(&map_rules);
+            let avoidance = avoidance.map(|a| a.0).unwrap_or_default();
+            let revision = (tile_map.last_changed(), occupancy.last_changed());
+            cache.path(
+                &tile_map,
+                &occupancy,
+                revision,
+                (*start, goal, rules, avoidance),
+            )
+        }
+        _ => None,
+    };
+    preview.set_if_neq(PathPreview(path));
+}
+
+/// Travelling walks the previewed path, which ends in an attack when it leads
+/// to a monster. Cancelling stops walking. Clicks on the UI never travel.
 pub fn cursor_clicked(
-    mouse: Res<ButtonInput<MouseButton>>,
+    actions: Res<ActionState>,
     mut commands: Commands,
-    player_query: Query<(Entity, Option<&Moving>), With<Player>>,
+    preview: Res<PathPreview>,
+    interactions: Query<&Interaction>,
+    mut player_query: Query<(Entity, &mut Target), With<Player>>,
 ) {
-    if !mouse.just_pressed(MouseButton::Left) {
+    let Ok((entity, mut target)) = player_query.single_mut() else {
         return;
-    }
+    };
 
-    if let Ok((entity, moving)) = player_query.single() {
-        if moving.is_none() {
-            commands.entity(entity).insert(Moving {
-                speed: 3.,
-                ..default()
-            });
-        } else {
-            commands.entity(entity).remove::<Moving>();
-        }
-    } else {
-        println!("cursor_clicked: Player not found!");
+    if actions.just_pressed(Action::Cancel) {
+        commands.entity(entity).remove::<Moving>();
+        target.path = None;
+        target.position = None;
+    } else if actions.just_pressed(Action::Travel)
+        && interactions.iter().all(|i| *i == Interaction::None)
+        && let Some(path) = preview.0.as_ref().filter(|path| path.len() > 1)
+    {
+        target.position = path.last().copied();
+        target.path = Some(path.clone());
+        commands.entity(entity).insert(Moving {
+            speed: 3.,
+            ..default()
+        });
     }
 }
@@ -1,3 +1,8 @@
+pub mod ai;
+pub mod camera;
+pub mod combat;
+pub mod fov;
 pub mod game_input;
 pub mod pathfinding;
 pub mod tile_map;
+pub mod turns;
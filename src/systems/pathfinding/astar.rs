@@ -0,0 +1,454 @@
+use crate::components::tiles::MapPosition;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Anything A* can search: a set of tiles with walk costs and connections.
+pub trait PathGrid {
+    /// Cost of stepping onto `pos`, or `None` if it can't be entered.
+    fn cost(&self, pos: MapPosition) -> Option<u32>;
+
+    /// Tiles orthogonally next to `pos`, walkable or not.
+    fn neighbors(&self, pos: MapPosition) -> impl Iterator<Item = MapPosition>;
+
+    /// Whether `pos` is solid, for the corner cutting rule.
+    fn is_blocking(&self, pos: MapPosition) -> bool {
+        self.cost(pos).is_none()
+    }
+}
+
+/// How a search treats tiles that other actors stand on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ActorAvoidance {
+    /// Never plan through them.
+    #[default]
+    Block,
+    /// Plan through them at this extra walk cost, so actors queue up behind
+    /// each other rather than take a long way round.
+    Penalty(u32),
+}
+
+/// Wraps a grid so tiles for which `occupied` returns true are avoided
+/// according to `avoidance`.
+pub struct OccupiedGrid<'a, G, F> {
+    pub grid: &'a G,
+    pub occupied: F,
+    pub avoidance: ActorAvoidance,
+}
+
+impl<G: PathGrid, F: Fn(MapPosition) -> bool> PathGrid for OccupiedGrid<'_, G, F> {
+    fn cost(&self, pos: MapPosition) -> Option<u32> {
+        let cost = self.grid.cost(pos)?;
+        if !(self.occupied)(pos) {
+            return Some(cost);
+        }
+        match self.avoidance {
+            ActorAvoidance::Block => None,
+            ActorAvoidance::Penalty(penalty) => Some(cost + penalty),
+        }
+    }
+
+    fn neighbors(&self, pos: MapPosition) -> impl Iterator<Item = MapPosition> {
+        self.grid.neighbors(pos)
+    }
+
+    // Actors never count as walls for corner cutting
+    fn is_blocking(&self, pos: MapPosition) -> bool {
+        self.grid.is_blocking(pos)
+    }
+}
+
+// Cost of an orthogonal step; diagonal costs are relative to it
+const ORTHOGONAL_COST: u32 = 100;
+
+/// How actors may step between tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MovementRules {
+    pub diagonals: bool,
+    /// Cost of a diagonal step, in percent of an orthogonal one.
+    pub diagonal_cost: u32,
+    /// Whether diagonal steps may pass a blocking tile beside them. Without it
+    /// a diagonal step needs both tiles it cuts past to be open.
+    pub corner_cutting: bool,
+}
+
+impl Default for MovementRules {
+    fn default() -> Self {
+        MovementRules {
+            diagonals: false,
+            diagonal_cost: 141,
+            corner_cutting: false,
+        }
+    }
+}
+
+impl MovementRules {
+    pub fn eight_way() -> Self {
+        MovementRules {
+            diagonals: true,
+            ..Default::default()
+        }
+    }
+
+    // Every tile one step from `pos` along with the cost of the step itself
+    pub(crate) fn steps(&self, grid: &impl PathGrid, pos: MapPosition) -> Vec<(MapPosition, u32)> {
+        let mut steps: Vec<_> = grid.neighbors(pos).map(|n| (n, ORTHOGONAL_COST)).collect();
+        if !self.diagonals {
+            return steps;
+        }
+        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let (Some(x), Some(y)) = (pos.x.checked_add_signed(dx), pos.y.checked_add_signed(dy))
+            else {
+                continue;
+            };
+            let cuts_corner = grid.is_blocking(MapPosition { x, y: pos.y })
+                || grid.is_blocking(MapPosition { x: pos.x, y });
+            if self.corner_cutting || !cuts_corner {
+                steps.push((MapPosition { x, y }, self.diagonal_cost));
+            }
+        }
+        steps
+    }
+
+    /// Walkable tiles an actor at `pos` can step to.
+    pub fn walkable_steps(&self, grid: &impl PathGrid, pos: MapPosition) -> Vec<MapPosition> {
+        self.steps(grid, pos)
+            .into_iter()
+            .map(|(step, _)| step)
+            .filter(|&step| grid.cost(step).is_some())
+            .collect()
+    }
+
+    // Octile distance when diagonals are allowed, Manhattan otherwise. Never
+    // overestimates as long as walk costs are at least one.
+    fn heuristic(&self, from: MapPosition, to: MapPosition) -> u32 {
+        let dx = from.x.abs_diff(to.x) as u32;
+        let dy = from.y.abs_diff(to.y) as u32;
+        let straight = ORTHOGONAL_COST * (dx + dy);
+        if !self.diagonals {
+            return straight;
+        }
+        let diagonal_cost = self.diagonal_cost.min(2 * ORTHOGONAL_COST);
+        straight - (2 * ORTHOGONAL_COST - diagonal_cost) * dx.min(dy)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    /// Every tile along the way, starting with the start and ending with the goal.
+    pub tiles: Vec<MapPosition>,
+    /// Sum of the walk costs of every tile entered.
+    pub cost: u32,
+}
+
+#[derive(Eq, PartialEq)]
+struct Node {
+    position: MapPosition,
+    cost: u32,
+    priority: u32,
+}
+
+// For BinaryHeap to be a min-heap
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .cmp(&self.priority)
+            .then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finds the cheapest path from `start` to `goal`, or `None` if the goal
+/// can't be reached. Each step costs the walk cost of the tile entered, scaled
+/// up for diagonal steps; the start tile itself is never charged for.
+pub fn astar(
+    grid: &impl PathGrid,
+    rules: &MovementRules,
+    start: MapPosition,
+    goal: MapPosition,
+) -> Option<Path> {
+    let mut frontier = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    frontier.push(Node {
+        position: start,
+        cost: 0,
+        priority: 0,
+    });
+    came_from.insert(start, None);
+    cost_so_far.insert(start, 0);
+
+    while let Some(Node { position, cost, .. }) = frontier.pop() {
+        if position == goal {
+            break;
+        }
+        if cost > cost_so_far[&position] {
+            continue; // Stale entry, a cheaper way here was found since
+        }
+
+        for (neighbor, step_cost) in rules.steps(grid, position) {
+            let Some(walk_cost) = grid.cost(neighbor) else {
+                continue;
+            };
+            let new_cost = cost + walk_cost * step_cost;
+            if cost_so_far.get(&neighbor).is_none_or(|&c| new_cost < c) {
+                cost_so_far.insert(neighbor, new_cost);
+                frontier.push(Node {
+                    position: neighbor,
+                    cost: new_cost,
+                    priority: new_cost + rules.heuristic(neighbor, goal),
+                });
+                came_from.insert(neighbor, Some(position));
+            }
+        }
+    }
+
+    cost_so_far.get(&goal)?;
+    let mut tiles = vec![goal];
+    let mut current = goal;
+    while let Some(prev) = came_from.get(&current).copied().flatten() {
+        tiles.push(prev);
+        current = prev;
+    }
+    tiles.reverse();
+    let cost = tiles[1..].iter().filter_map(|&tile| grid.cost(tile)).sum();
+    Some(Path { tiles, cost })
+}
+
+/// Patches a path whose tiles may have become impassable since it was planned.
+/// Only the broken stretch is searched again, from the last good tile to the
+/// first walkable one after it; if there is no way around, the whole path is
+/// planned again. Returns the path unchanged when every step is still valid.
+pub fn repair_path(
+    grid: &impl PathGrid,
+    rules: &MovementRules,
+    path: &[MapPosition],
+) -> Option<Vec<MapPosition>> {
+    let (&start, rest) = path.split_first()?;
+    let &goal = rest.last().unwrap_or(&start);
+    let broken = path
+        .windows(2)
+        .position(|step| !rules.walkable_steps(grid, step[0]).contains(&step[1]));
+    let Some(broken) = broken else {
+        return Some(path.to_vec());
+    };
+
+    // `path[broken]` is the last tile that can still be reached as planned
+    let rejoin = (broken + 1..path.len()).find(|&i| grid.cost(path[i]).is_some())?;
+    if let Some(detour) = astar(grid, rules, path[broken], path[rejoin]) {
+        let mut repaired = path[..broken].to_vec();
+        repaired.extend(detour.tiles);
+        repaired.extend(&path[rejoin + 1..]);
+        return Some(repaired);
+    }
+    astar(grid, rules, start, goal).map(|path| path.tiles)
+}
+
+pub fn manhattan_distance(a: MapPosition, b: MapPosition) -> u32 {
+    ((a.x as isize - b.x as isize).abs() + (a.y as isize - b.y as isize).abs()) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::pathfinding::test_grid::{TestGrid, pos};
+
+    #[test]
+    fn finds_straight_path() {
+        let grid = TestGrid::new("1111");
+        let path = astar(&grid, &MovementRules::default(), pos(0, 0), pos(3, 0)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 0), pos(1, 0), pos(2, 0), pos(3, 0)]);
+        assert_eq!(path.cost, 3);
+    }
+
+    #[test]
+    fn start_is_goal() {
+        let grid = TestGrid::new("1");
+        let path = astar(&grid, &MovementRules::default(), pos(0, 0), pos(0, 0)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 0)]);
+        assert_eq!(path.cost, 0);
+    }
+
+    #[test]
+    fn blocked_goal_has_no_path() {
+        let grid = TestGrid::new("11#");
+        assert_eq!(
+            astar(&grid, &MovementRules::default(), pos(0, 0), pos(2, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let grid = TestGrid::new(
+            "\
+1#1
+1#1
+1#1",
+        );
+        assert_eq!(
+            astar(&grid, &MovementRules::default(), pos(0, 0), pos(2, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn goes_around_expensive_terrain() {
+        let grid = TestGrid::new(
+            "\
+111
+191
+111",
+        );
+        let path = astar(&grid, &MovementRules::default(), pos(1, 0), pos(1, 2)).unwrap();
+        assert_eq!(path.cost, 4);
+        assert!(!path.tiles.contains(&pos(1, 1)));
+    }
+
+    #[test]
+    fn walks_through_terrain_cheaper_than_the_detour() {
+        let grid = TestGrid::new(
+            "\
+111
+121
+111",
+        );
+        let path = astar(&grid, &MovementRules::default(), pos(1, 0), pos(1, 2)).unwrap();
+        assert_eq!(path.cost, 3);
+        assert_eq!(path.tiles, vec![pos(1, 0), pos(1, 1), pos(1, 2)]);
+    }
+
+    #[test]
+    fn diagonals_cut_across_open_ground() {
+        let grid = TestGrid::new(
+            "\
+111
+111
+111",
+        );
+        let path = astar(&grid, &MovementRules::eight_way(), pos(0, 0), pos(2, 2)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 0), pos(1, 1), pos(2, 2)]);
+    }
+
+    #[test]
+    fn expensive_diagonals_are_avoided() {
+        let grid = TestGrid::new(
+            "\
+11
+11",
+        );
+        let rules = MovementRules {
+            diagonal_cost: 250,
+            ..MovementRules::eight_way()
+        };
+        let path = astar(&grid, &rules, pos(0, 0), pos(1, 1)).unwrap();
+        assert_eq!(path.tiles.len(), 3);
+    }
+
+    #[test]
+    fn diagonals_dont_cut_past_a_single_wall() {
+        let grid = TestGrid::new(
+            "\
+11
+1#
+11",
+        );
+        let rules = MovementRules::eight_way();
+        let path = astar(&grid, &rules, pos(0, 1), pos(1, 2)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 1), pos(0, 2), pos(1, 2)]);
+        let steps = rules.walkable_steps(&grid, pos(0, 1));
+        assert!(!steps.contains(&pos(1, 0)) && !steps.contains(&pos(1, 2)));
+    }
+
+    #[test]
+    fn no_squeezing_between_blocking_corners() {
+        let grid = TestGrid::new(
+            "\
+#1
+1#",
+        );
+        let rules = MovementRules::eight_way();
+        assert_eq!(astar(&grid, &rules, pos(0, 0), pos(1, 1)), None);
+
+        let squeeze = MovementRules {
+            corner_cutting: true,
+            ..rules
+        };
+        let path = astar(&grid, &squeeze, pos(0, 0), pos(1, 1)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 0), pos(1, 1)]);
+    }
+
+    #[test]
+    fn repair_keeps_valid_paths() {
+        let grid = TestGrid::new("111");
+        let path = vec![pos(0, 0), pos(1, 0), pos(2, 0)];
+        let rules = MovementRules::default();
+        assert_eq!(repair_path(&grid, &rules, &path), Some(path));
+    }
+
+    #[test]
+    fn repair_detours_around_new_walls() {
+        let grid = TestGrid::new(
+            "\
+1111
+1#11",
+        );
+        let rules = MovementRules::default();
+        let path = vec![pos(0, 0), pos(1, 0), pos(2, 0), pos(3, 0)];
+        let repaired = repair_path(&grid, &rules, &path).unwrap();
+        assert_eq!(
+            repaired,
+            vec![
+                pos(0, 0),
+                pos(0, 1),
+                pos(1, 1),
+                pos(2, 1),
+                pos(2, 0),
+                pos(3, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn repair_fails_when_the_goal_is_walled_in() {
+        let grid = TestGrid::new("11#1");
+        let path = vec![pos(0, 0), pos(1, 0), pos(2, 0), pos(3, 0)];
+        assert_eq!(repair_path(&grid, &MovementRules::default(), &path), None);
+    }
+
+    #[test]
+    fn occupied_tiles_block_or_cost_extra() {
+        let grid = TestGrid::new(
+            "\
+111
+111",
+        );
+        let rules = MovementRules::default();
+        let occupied_grid = |avoidance| OccupiedGrid {
+            grid: &grid,
+            occupied: |pos| pos == MapPosition { x: 1, y: 0 },
+            avoidance,
+        };
+
+        let around = astar(
+            &occupied_grid(ActorAvoidance::Block),
+            &rules,
+            pos(0, 0),
+            pos(2, 0),
+        );
+        assert_eq!(around.unwrap().tiles.len(), 5);
+
+        let queued = astar(
+            &occupied_grid(ActorAvoidance::Penalty(1)),
+            &rules,
+            pos(0, 0),
+            pos(2, 0),
+        );
+        assert_eq!(queued.unwrap().cost, 3);
+    }
+}
@@ -0,0 +1,121 @@
+use super::astar::{MovementRules, PathGrid};
+use crate::components::tiles::MapPosition;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Distance from every reachable tile to the nearest goal, also known as a
+/// Dijkstra map. Actors walk downhill to close in on a goal and uphill to get
+/// away from it, so one map serves any number of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DijkstraMap {
+    distances: HashMap<MapPosition, u32>,
+}
+
+impl DijkstraMap {
+    /// Floods out from `goals` over every walkable tile, costing steps the same
+    /// way `astar` does.
+    pub fn new(grid: &impl PathGrid, rules: &MovementRules, goals: &[MapPosition]) -> Self {
+        let mut distances = HashMap::new();
+        let mut frontier = BinaryHeap::new();
+        for &goal in goals {
+            distances.insert(goal, 0);
+            frontier.push(Reverse((0, goal)));
+        }
+
+        while let Some(Reverse((distance, position))) = frontier.pop() {
+            if distance > distances[&position] {
+                continue; // Stale entry
+            }
+            for (neighbor, step_cost) in rules.steps(grid, position) {
+                let Some(walk_cost) = grid.cost(neighbor) else {
+                    continue;
+                };
+                let new_distance = distance + walk_cost * step_cost;
+                if distances.get(&neighbor).is_none_or(|&d| new_distance < d) {
+                    distances.insert(neighbor, new_distance);
+                    frontier.push(Reverse((new_distance, neighbor)));
+                }
+            }
+        }
+
+        DijkstraMap { distances }
+    }
+
+    /// Distance to the nearest goal, or `None` if `pos` can't reach one.
+    pub fn distance(&self, pos: MapPosition) -> Option<u32> {
+        self.distances.get(&pos).copied()
+    }
+
+    /// The step from `pos` that gets closest to a goal, if any gets closer.
+    pub fn downhill(
+        &self,
+        candidates: impl IntoIterator<Item = MapPosition>,
+        pos: MapPosition,
+    ) -> Option<MapPosition> {
+        let current = self.distance(pos).unwrap_or(u32::MAX);
+        candidates
+            .into_iter()
+            .filter_map(|step| Some((self.distance(step)?, step)))
+            .filter(|(distance, _)| *distance < current)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, step)| step)
+    }
+
+    /// The step from `pos` that gets furthest from every goal, if any does.
+    pub fn uphill(
+        &self,
+        candidates: impl IntoIterator<Item = MapPosition>,
+        pos: MapPosition,
+    ) -> Option<MapPosition> {
+        let current = self.distance(pos)?;
+        candidates
+            .into_iter()
+            .filter_map(|step| Some((self.distance(step)?, step)))
+            .filter(|(distance, _)| *distance > current)
+            .max_by_key(|(distance, _)| *distance)
+            .map(|(_, step)| step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::pathfinding::test_grid::{TestGrid, pos};
+
+    #[test]
+    fn distances_follow_walk_costs() {
+        let grid = TestGrid::new("1131");
+        let map = DijkstraMap::new(&grid, &MovementRules::default(), &[pos(0, 0)]);
+        let distances: Vec<_> = (0..4).map(|x| map.distance(pos(x, 0))).collect();
+        assert_eq!(distances, vec![Some(0), Some(100), Some(400), Some(500)]);
+    }
+
+    #[test]
+    fn walls_cut_off_the_goal() {
+        let grid = TestGrid::new("1#1");
+        let map = DijkstraMap::new(&grid, &MovementRules::default(), &[pos(0, 0)]);
+        assert_eq!(map.distance(pos(1, 0)), None);
+        assert_eq!(map.distance(pos(2, 0)), None);
+    }
+
+    #[test]
+    fn rolling_downhill_and_uphill() {
+        let grid = TestGrid::new(
+            "\
+111
+1#1
+111",
+        );
+        let rules = MovementRules::default();
+        let map = DijkstraMap::new(&grid, &rules, &[pos(0, 0)]);
+
+        let mut position = pos(2, 2);
+        while let Some(step) = map.downhill(rules.walkable_steps(&grid, position), position) {
+            position = step;
+        }
+        assert_eq!(position, pos(0, 0));
+
+        let away = map.uphill(rules.walkable_steps(&grid, pos(1, 0)), pos(1, 0));
+        assert_eq!(away, Some(pos(2, 0)));
+    }
+}
@@ -1,190 +1,445 @@
-use crate::components::attributes::Moving;
+pub mod astar;
+pub mod flow_field;
+#[cfg(test)]
+mod test_grid;
+
+use crate::components::attributes::{Energy, Moving};
+use crate::components::basic::{Faction, Player, TakingTurn};
 use crate::components::tiles::*;
-use bevy::prelude::*;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
-
-#[derive(Eq, PartialEq)]
-struct Node {
-    position: MapPosition,
-    cost: u32,
-    priority: u32,
-}
+use crate::events::AttackEvent;
+use crate::systems::tile_map::{
+    generation::arg_value,
+    grid::{TileKind, TileMap},
+};
+use crate::systems::turns::spend_turn;
+use astar::{ActorAvoidance, MovementRules, OccupiedGrid, PathGrid, astar, repair_path};
+use bevy::{ecs::component::Tick, prelude::*};
+use flow_field::DijkstraMap;
+use std::collections::HashMap;
+
+/// `MovementRules` for the ECS. The resource holds the rules for the current
+/// map; actors with the component move by their own rules instead.
+#[derive(Resource, Component, Deref, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Movement(pub MovementRules);
 
-// For BinaryHeap to be a min-heap
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other
-            .priority
-            .cmp(&self.priority)
-            .then_with(|| self.cost.cmp(&other.cost))
+impl Movement {
+    /// Picks the default rules from `--movement <orthogonal|eight-way>`.
+    pub fn from_args() -> Self {
+        match arg_value("--movement").as_deref() {
+            Some("eight-way") => Movement(MovementRules::eight_way()),
+            Some("orthogonal") | None => Movement::default(),
+            Some(other) => {
+                warn!("Unknown movement '{other}', using orthogonal");
+                Movement::default()
+            }
+        }
     }
 }
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+
+/// How an actor's paths treat the tiles other actors stand on.
+#[derive(Component, Deref, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Avoidance(pub ActorAvoidance);
+
+// Hovering around the map asks for the same few paths over and over
+const PATH_CACHE_CAPACITY: usize = 1024;
+
+type PathKey = (MapPosition, MapPosition, MovementRules, ActorAvoidance);
+
+/// The change ticks of the `TileMap` and the `Occupancy`. Both only change
+/// when actually rebuilt, so their ticks double as revision numbers.
+pub type MapRevision = (Tick, Tick);
+
+/// Results of earlier searches, valid until the terrain or the actors on it
+/// change.
+#[derive(Resource, Default)]
+pub struct PathCache {
+    revision: Option<MapRevision>,
+    paths: HashMap<PathKey, Option<Vec<MapPosition>>>,
 }
 
-fn build_tile_lookup(
-    tiles: &Query<(&MapPosition, Option<&Walkable>, Option<&Blocking>)>,
-) -> HashMap<MapPosition, (bool, Option<u32>)> {
-    let mut lookup = HashMap::new();
-
-    for (tile_pos, maybe_walkable, maybe_blocking) in tiles.iter() {
-        lookup
-            .entry(*tile_pos)
-            .and_modify(|(blocked, walkable)| {
-                if maybe_blocking.is_some() {
-                    *blocked = true;
-                }
-                if let Some(w) = maybe_walkable {
-                    *walkable = Some(w.cost.max(1));
-                }
-            })
+impl PathCache {
+    /// The path for `key`, searched on `tile_map` and `occupancy` unless it
+    /// was already found at the same `revision` of them.
+    pub fn path(
+        &mut self,
+        tile_map: &TileMap,
+        occupancy: &Occupancy,
+        revision: MapRevision,
+        key: PathKey,
+    ) -> Option<Vec<MapPosition>> {
+        if self.revision != Some(revision) || self.paths.len() >= PATH_CACHE_CAPACITY {
+            self.revision = Some(revision);
+            self.paths.clear();
+        }
+        self.paths
+            .entry(key)
             .or_insert_with(|| {
-                (
-                    maybe_blocking.is_some(),
-                    maybe_walkable.map(|w| w.cost.max(1)),
-                )
-            });
+                let (start, goal, rules, avoidance) = key;
+                // The goal stays reachable even with someone standing on it, so
+                // actors can walk up to whatever they are after
+                let grid = OccupiedGrid {
+                    grid: tile_map,
+                    occupied: |pos| pos != goal && occupancy.is_occupied(pos),
+                    avoidance,
+                };
+                astar(&grid, &rules, start, goal).map(|path| path.tiles)
+            })
+            .clone()
+    }
+}
+
+/// Which actor stands on which tile.
+#[derive(Resource, Default, PartialEq)]
+pub struct Occupancy(HashMap<MapPosition, Entity>);
+
+impl Occupancy {
+    pub fn get(&self, pos: MapPosition) -> Option<Entity> {
+        self.0.get(&pos).copied()
     }
 
-    lookup
+    pub fn is_occupied(&self, pos: MapPosition) -> bool {
+        self.0.contains_key(&pos)
+    }
 }
 
+pub fn update_occupancy(
+    mut occupancy: ResMut<Occupancy>,
+    actors: Query<(Entity, &MapPosition), With<Faction>>,
+) {
+    let current = Occupancy(actors.iter().map(|(e, pos)| (*pos, e)).collect());
+    occupancy.set_if_neq(current);
+}
+
+type PathSeeker<'a> = (
+    &'a MapPosition,
+    &'a mut Target,
+    Option<&'a Movement>,
+    Option<&'a Avoidance>,
+    Has<Moving>,
+);
+
+/// Plans paths to each actor's target, steering around other actors as the
+/// actor's `Avoidance` asks. Paths being walked stay as they are, except
+/// that they get patched up when the terrain under them changes.
 pub fn find_path(
-    mut target_query: Query<(&MapPosition, &mut Target), Without<Moving>>,
-    tiles: Query<(&MapPosition, Option<&Walkable>, Option<&Blocking>)>,
+    mut target_query: Query<PathSeeker>,
+    tile_map: Option<Res<TileMap>>,
+    map_rules: Res<Movement>,
+    occupancy: Res<Occupancy>,
+    mut cache: ResMut<PathCache>,
 ) {
-    let tile_lookup = build_tile_lookup(&tiles);
+    let Some(tile_map) = tile_map else {
+        return;
+    };
+
+    for (start_pos, mut target, rules, avoidance, moving) in target_query.iter_mut() {
+        let rules = rules.unwrap_or(&map_rules);
+        if moving {
+            if tile_map.is_changed()
+                && let Some(path) = target.path.take()
+            {
+                target.path = repair_path(&*tile_map, rules, &path);
+            }
+            continue;
+        }
 
-    for (start_pos, mut target) in target_query.iter_mut() {
         let Some(goal_pos) = target.position else {
             continue;
         };
-        if Some(goal_pos) == target.path.as_ref().and_then(|p| p.last().copied()) {
+        let planned = target.path.as_ref().and_then(|p| p.last().copied());
+        if planned == Some(goal_pos) && !tile_map.is_changed() && !occupancy.is_changed() {
             continue; // Already at destination
         }
 
-        let mut frontier = BinaryHeap::new();
-        let mut came_from = HashMap::new();
-        let mut cost_so_far = HashMap::new();
+        let avoidance = avoidance.map(|a| a.0).unwrap_or_default();
+        target.path = cache.path(
+            &tile_map,
+            &occupancy,
+            (tile_map.last_changed(), occupancy.last_changed()),
+            (*start_pos, goal_pos, **rules, avoidance),
+        );
+    }
+}
 
-        frontier.push(Node {
-            position: *start_pos,
-            cost: 0,
-            priority: 0,
-        });
-        came_from.insert(*start_pos, None);
-        cost_so_far.insert(*start_pos, 0);
+/// Distances to the player under the map's movement rules, shared by every
+/// monster that chases or flees.
+#[derive(Resource, Default)]
+pub struct PlayerFlowField(pub DijkstraMap);
 
-        while let Some(Node { position, .. }) = frontier.pop() {
-            if position == goal_pos {
-                break;
-            }
+/// Rebuilds the flow field, but only once the player has moved or the
+/// terrain or movement rules have changed.
+pub fn update_flow_field(
+    tile_map: Option<Res<TileMap>>,
+    rules: Res<Movement>,
+    player: Query<Ref<MapPosition>, With<Player>>,
+    mut flow_field: ResMut<PlayerFlowField>,
+) {
+    let (Some(tile_map), Ok(player_pos)) = (tile_map, player.single()) else {
+        return;
+    };
+    if player_pos.is_changed() || tile_map.is_changed() || rules.is_changed() {
+        flow_field.0 = DijkstraMap::new(&*tile_map, &rules, &[*player_pos]);
+    }
+}
 
-            for neighbor in neighbors(position) {
-                if let Some(walkable_cost) = get_walkable_cost(&neighbor, &tile_lookup) {
-                    let new_cost = cost_so_far.get(&position).unwrap() + walkable_cost;
-                    if cost_so_far.get(&neighbor).map_or(true, |&c| new_cost < c) {
-                        cost_so_far.insert(neighbor, new_cost);
-                        let priority = new_cost + manhattan_distance(neighbor, goal_pos);
-                        frontier.push(Node {
-                            position: neighbor,
-                            cost: new_cost,
-                            priority,
-                        });
-                        came_from.insert(neighbor, Some(position));
-                    }
-                }
-            }
-        }
+impl PathGrid for TileMap {
+    fn cost(&self, pos: MapPosition) -> Option<u32> {
+        self.walk_cost(pos)
+    }
 
-        // Reconstruct path
-        let mut path = Vec::new();
-        let mut current = goal_pos;
-        while let Some(prev) = came_from.get(&current).copied().flatten() {
-            path.push(current);
-            current = prev;
+    fn neighbors(&self, pos: MapPosition) -> impl Iterator<Item = MapPosition> {
+        self.orthogonal_neighbors(pos)
+    }
+
+    // Solid rock isn't a `Blocking` wall, but nothing can cut past it either
+    fn is_blocking(&self, pos: MapPosition) -> bool {
+        self.is_blocking(pos) || self.kind(pos) == TileKind::Empty
+    }
+}
+
+type Walker<'a> = (
+    Entity,
+    &'a mut MapPosition,
+    &'a mut Target,
+    &'a mut Moving,
+    &'a mut Energy,
+    Option<&'a Faction>,
+    Option<&'a Movement>,
+);
+
+/// Auto-move: follows the current path one tile per turn, pausing between
+/// steps so the movement stays readable. When someone stands on the next
+/// step, hostile actors get attacked; anyone else is walked around if there
+/// is a way, or waited for if there isn't.
+#[allow(clippy::too_many_arguments)]
+pub fn move_along_path(
+    mut commands: Commands,
+    time: Res<Time>,
+    tile_map: Option<Res<TileMap>>,
+    map_rules: Res<Movement>,
+    occupancy: Res<Occupancy>,
+    factions: Query<&Faction>,
+    mut query: Query<Walker, With<TakingTurn>>,
+    mut attacks: EventWriter<AttackEvent>,
+) {
+    let Some(tile_map) = tile_map else {
+        return;
+    };
+    for (entity, mut map_pos, mut target, mut moving, mut energy, faction, rules) in
+        query.iter_mut()
+    {
+        moving.timer.tick(time.delta());
+        if !moving.timer.finished() {
+            continue;
         }
 
-        if current == *start_pos {
-            path.push(*start_pos);
-            path.reverse();
-            target.path = Some(path);
-        } else {
+        let Some(path) = target.path.as_mut().filter(|path| path.len() > 1) else {
+            // Arrived, or there is no way there
             target.path = None;
+            target.position = None;
+            commands.entity(entity).remove::<Moving>();
+            continue;
+        };
+        moving.timer = Timer::from_seconds(1.0 / moving.speed, TimerMode::Once);
+
+        let occupant = occupancy.get(path[1]).filter(|&other| other != entity);
+        if let Some(occupant) = occupant {
+            let hostile = faction
+                .zip(factions.get(occupant).ok())
+                .is_some_and(|(ours, theirs)| ours.is_hostile_to(*theirs));
+            if hostile {
+                attacks.write(AttackEvent {
+                    attacker: entity,
+                    defender: occupant,
+                });
+            } else {
+                let rules = rules.unwrap_or(&map_rules);
+                let grid = OccupiedGrid {
+                    grid: &*tile_map,
+                    occupied: |pos| pos != path[0] && occupancy.is_occupied(pos),
+                    avoidance: ActorAvoidance::Block,
+                };
+                if let Some(detour) = repair_path(&grid, rules, path) {
+                    *path = detour;
+                }
+            }
+            // Attacking or waiting uses up the turn as well
+            if hostile || occupancy.is_occupied(path[1]) {
+                spend_turn(&mut commands, entity, &mut energy);
+                continue;
+            }
         }
+
+        path.remove(0); // drop current
+        *map_pos = path[0];
+        spend_turn(&mut commands, entity, &mut energy);
     }
 }
 
-fn neighbors(pos: MapPosition) -> Vec<MapPosition> {
-    let mut neighbors = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::basic::Visible;
+    use crate::systems::tile_map::grid::MapSize;
+    use crate::systems::turns::interrupt_auto_move;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+    use test_grid::pos;
 
-    if pos.x > 0 {
-        neighbors.push(MapPosition {
-            x: pos.x - 1,
-            y: pos.y,
+    // An open 3x2 room
+    fn room() -> World {
+        let mut tile_map = TileMap::new(MapSize {
+            width: 3,
+            height: 2,
         });
+        for pos in tile_map.positions() {
+            tile_map.set_kind(pos, TileKind::Floor);
+        }
+        let mut world = World::new();
+        world.insert_resource(tile_map);
+        world.insert_resource(Movement::default());
+        world.init_resource::<Occupancy>();
+        world.init_resource::<PathCache>();
+        world
     }
 
-    if pos.x < 31 {
-        neighbors.push(MapPosition {
-            x: pos.x + 1,
-            y: pos.y,
-        });
+    fn cached_path(world: &mut World, goal: MapPosition) -> Option<Vec<MapPosition>> {
+        let key = (
+            pos(0, 0),
+            goal,
+            MovementRules::default(),
+            ActorAvoidance::Block,
+        );
+        world.resource_scope(|world, mut cache: Mut<PathCache>| {
+            let tile_map = world.resource_ref::<TileMap>();
+            let occupancy = world.resource_ref::<Occupancy>();
+            let revision = (tile_map.last_changed(), occupancy.last_changed());
+            let path = cache.path(&tile_map, &occupancy, revision, key);
+            // Later changes happen on a later frame
+            world.increment_change_tick();
+            path
+        })
     }
 
-    if pos.y > 0 {
-        neighbors.push(MapPosition {
-            x: pos.x,
-            y: pos.y - 1,
-        });
+    #[test]
+    fn repeated_searches_come_from_the_cache() {
+        let mut world = room();
+        assert_eq!(cached_path(&mut world, pos(2, 0)).unwrap().len(), 3);
+        // Only a cache hit can give back this made up answer
+        let made_up = Some(vec![pos(0, 0)]);
+        for path in world.resource_mut::<PathCache>().paths.values_mut() {
+            *path = made_up.clone();
+        }
+        assert_eq!(cached_path(&mut world, pos(2, 0)), made_up);
     }
 
-    if pos.y < 31 {
-        neighbors.push(MapPosition {
-            x: pos.x,
-            y: pos.y + 1,
-        });
+    #[test]
+    fn map_changes_invalidate_cached_paths() {
+        let mut world = room();
+        assert_eq!(cached_path(&mut world, pos(2, 0)).unwrap().len(), 3);
+        world
+            .resource_mut::<TileMap>()
+            .set_kind(pos(1, 0), TileKind::Wall);
+        assert_eq!(cached_path(&mut world, pos(2, 0)).unwrap().len(), 5);
     }
 
-    neighbors
-}
+    #[test]
+    fn occupancy_changes_invalidate_cached_paths() {
+        let mut world = room();
+        assert_eq!(cached_path(&mut world, pos(2, 0)).unwrap().len(), 3);
+        world
+            .resource_mut::<Occupancy>()
+            .0
+            .insert(pos(1, 0), Entity::PLACEHOLDER);
+        assert_eq!(cached_path(&mut world, pos(2, 0)).unwrap().len(), 5);
+    }
 
-fn manhattan_distance(a: MapPosition, b: MapPosition) -> u32 {
-    ((a.x as isize - b.x as isize).abs() + (a.y as isize - b.y as isize).abs()) as u32
-}
+    #[test]
+    fn walked_paths_are_repaired_when_the_map_changes() {
+        let mut world = room();
+        let walker = world
+            .spawn((
+                pos(0, 0),
+                Target {
+                    path: Some(vec![pos(0, 0), pos(1, 0), pos(2, 0)]),
+                    position: Some(pos(2, 0)),
+                },
+                Moving::default(),
+            ))
+            .id();
+        world
+            .resource_mut::<TileMap>()
+            .set_kind(pos(1, 0), TileKind::Wall);
+        world.run_system_once(find_path).unwrap();
+        assert_eq!(
+            world.get::<Target>(walker).unwrap().path,
+            Some(vec![pos(0, 0), pos(0, 1), pos(1, 1), pos(2, 1), pos(2, 0)])
+        );
+    }
 
-fn get_walkable_cost(
-    pos: &MapPosition,
-    lookup: &HashMap<MapPosition, (bool, Option<u32>)>,
-) -> Option<u32> {
-    match lookup.get(pos) {
-        Some((true, _)) => None, // Blocked
-        Some((false, Some(cost))) => Some(*cost),
-        _ => None,
+    #[test]
+    fn diagonals_dont_cut_past_solid_rock() {
+        let mut tile_map = TileMap::new(MapSize {
+            width: 2,
+            height: 2,
+        });
+        for floor in [pos(0, 0), pos(1, 0), pos(1, 1)] {
+            tile_map.set_kind(floor, TileKind::Floor);
+        }
+        let rules = MovementRules::eight_way();
+        let path = astar(&tile_map, &rules, pos(0, 0), pos(1, 1)).unwrap();
+        assert_eq!(path.tiles, vec![pos(0, 0), pos(1, 0), pos(1, 1)]);
     }
-}
 
-pub fn move_along_path(
-    time: Res<Time>,
-    mut query: Query<(&mut MapPosition, &mut Target, &mut Moving)>,
-) {
-    for (mut map_pos, mut target, mut moving) in query.iter_mut() {
-        moving.timer.tick(time.delta());
+    #[test]
+    fn travelling_to_a_monster_in_view_walks_up_and_attacks_it() {
+        let mut world = room();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<AttackEvent>>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((interrupt_auto_move, update_occupancy, move_along_path).chain());
+        let monster = world
+            .spawn((pos(2, 0), Faction::Monsters, Visible::InView))
+            .id();
+        let player = world
+            .spawn((
+                Player,
+                pos(0, 0),
+                Faction::Heroes,
+                Energy::default(),
+                Target {
+                    path: None,
+                    position: None,
+                },
+            ))
+            .id();
+        // The monster has been in view for a while before the click
+        schedule.run(&mut world);
 
-        if moving.timer.finished() {
-            if let Some(path) = target.path.as_mut() {
-                if path.len() > 1 {
-                    path.remove(0); // drop current
-                    *map_pos = path[0];
-                    moving.timer = Timer::from_seconds(1.0 / moving.speed, TimerMode::Once);
-                } else {
-                    target.path = None;
-                }
-            }
+        // What clicking on the monster commits to
+        let mut entity = world.entity_mut(player);
+        *entity.get_mut::<Target>().unwrap() = Target {
+            path: Some(vec![pos(0, 0), pos(1, 0), pos(2, 0)]),
+            position: Some(pos(2, 0)),
+        };
+        entity.insert(Moving {
+            speed: 3.,
+            ..default()
+        });
+        for _ in 0..2 {
+            world.entity_mut(player).insert(TakingTurn);
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs(1));
+            schedule.run(&mut world);
         }
+
+        assert_eq!(*world.get::<MapPosition>(player).unwrap(), pos(1, 0));
+        let attacks: Vec<_> = world
+            .resource_mut::<Events<AttackEvent>>()
+            .drain()
+            .map(|attack| (attack.attacker, attack.defender))
+            .collect();
+        assert_eq!(attacks, vec![(player, monster)]);
     }
 }
@@ -0,0 +1,37 @@
+use super::astar::PathGrid;
+use crate::components::tiles::MapPosition;
+
+// Rows are listed top to bottom like the ASCII levels: '#' is blocked and
+// digits are walk costs
+pub struct TestGrid {
+    rows: Vec<Vec<char>>,
+}
+
+impl TestGrid {
+    pub fn new(map: &str) -> Self {
+        TestGrid {
+            rows: map.lines().rev().map(|row| row.chars().collect()).collect(),
+        }
+    }
+}
+
+impl PathGrid for TestGrid {
+    fn cost(&self, pos: MapPosition) -> Option<u32> {
+        self.rows.get(pos.y)?.get(pos.x)?.to_digit(10)
+    }
+
+    fn neighbors(&self, pos: MapPosition) -> impl Iterator<Item = MapPosition> {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| {
+                Some(MapPosition {
+                    x: pos.x.checked_add_signed(dx)?,
+                    y: pos.y.checked_add_signed(dy)?,
+                })
+            })
+    }
+}
+
+pub fn pos(x: usize, y: usize) -> MapPosition {
+    MapPosition { x, y }
+}
@@ -0,0 +1,443 @@
+use crate::{
+    asset_manager::{SheetId, TileSheets},
+    components::tiles::{MapPosition, SheetSprite},
+    entities::MonsterKind,
+    sprite_catalog::{SpriteCatalog, names},
+    systems::tile_map::{
+        grid::MapSize,
+        level::{LevelAsset, LevelMonster, LevelTile},
+    },
+};
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::{FromWorld, World},
+};
+use std::{collections::HashMap, fmt};
+
+// Text levels look like this; the legend section is optional and overrides
+// or extends the default glyphs below. Sprites are given by their name in the
+// sprite catalog, or as a sheet and cell. Monsters name their kind first and
+// only need a sprite to look different from the rest of their kind:
+//
+// [legend]
+// # = wall wall_stone
+// r = monster rat
+// M = monster skeleton monsters 6 0
+// [map]
+// #####
+// #.@M#
+// #####
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GlyphKind {
+    Empty,
+    Floor,
+    Wall,
+    Door,
+    Player,
+    Monster(MonsterKind),
+}
+
+#[derive(Debug, Clone)]
+struct Glyph {
+    kind: GlyphKind,
+    sprite: Option<SheetSprite>,
+}
+
+fn default_legend(catalog: &SpriteCatalog) -> HashMap<char, Glyph> {
+    use GlyphKind::*;
+    let sprite = |name| Some(SheetSprite::named(catalog, name));
+    HashMap::from([
+        (
+            ' ',
+            Glyph {
+                kind: Empty,
+                sprite: None,
+            },
+        ),
+        (
+            '.',
+            Glyph {
+                kind: Floor,
+                sprite: sprite(names::FLOOR),
+            },
+        ),
+        (
+            '#',
+            Glyph {
+                kind: Wall,
+                sprite: sprite(names::WALL),
+            },
+        ),
+        (
+            '+',
+            Glyph {
+                kind: Door,
+                sprite: sprite(names::DOOR),
+            },
+        ),
+        (
+            '@',
+            Glyph {
+                kind: Player,
+                sprite: None,
+            },
+        ),
+        (
+            'M',
+            Glyph {
+                kind: Monster(MonsterKind::Goblin),
+                sprite: None,
+            },
+        ),
+    ])
+}
+
+#[derive(Debug)]
+pub enum AsciiLevelError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for AsciiLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiLevelError::Io(err) => write!(f, "could not read level: {err}"),
+            AsciiLevelError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for AsciiLevelError {}
+
+impl From<std::io::Error> for AsciiLevelError {
+    fn from(err: std::io::Error) -> Self {
+        AsciiLevelError::Io(err)
+    }
+}
+
+fn parse_error(line: usize, message: impl Into<String>) -> AsciiLevelError {
+    AsciiLevelError::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+fn parse_legend_entry(
+    line: usize,
+    text: &str,
+    catalog: &SpriteCatalog,
+    sheets: &TileSheets,
+) -> Result<(char, Glyph), AsciiLevelError> {
+    let (glyph, definition) = text
+        .split_once('=')
+        .ok_or_else(|| parse_error(line, "expected `<glyph> = <kind> [sprite]`"))?;
+    let mut glyph_chars = glyph.trim().chars();
+    let (Some(glyph), None) = (glyph_chars.next(), glyph_chars.next()) else {
+        return Err(parse_error(line, "legend glyph must be a single character"));
+    };
+
+    let parts: Vec<&str> = definition.split_whitespace().collect();
+    let (kind, sprite) = match parts[..] {
+        ["empty", ref sprite @ ..] => (GlyphKind::Empty, sprite),
+        ["floor", ref sprite @ ..] => (GlyphKind::Floor, sprite),
+        ["wall", ref sprite @ ..] => (GlyphKind::Wall, sprite),
+        ["door", ref sprite @ ..] => (GlyphKind::Door, sprite),
+        ["player", ref sprite @ ..] => (GlyphKind::Player, sprite),
+        ["monster", monster, ref sprite @ ..] => {
+            let monster = MonsterKind::from_name(monster)
+                .ok_or_else(|| parse_error(line, format!("unknown monster kind '{monster}'")))?;
+            (GlyphKind::Monster(monster), sprite)
+        }
+        ["monster"] => return Err(parse_error(line, "missing monster kind")),
+        [other, ..] => return Err(parse_error(line, format!("unknown tile kind '{other}'"))),
+        [] => return Err(parse_error(line, "missing tile kind")),
+    };
+
+    let sprite = match *sprite {
+        [] => None,
+        [name] => Some(
+            catalog
+                .get(name)
+                .ok_or_else(|| parse_error(line, format!("unknown sprite '{name}'")))?
+                .sprite(),
+        ),
+        [sheet, x, y] => {
+            let sheet = SheetId::from(sheet);
+            if !sheets.0.contains_key(&sheet) {
+                return Err(parse_error(line, format!("unknown tilesheet '{sheet}'")));
+            }
+            let coordinate = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| parse_error(line, format!("invalid sheet coordinate '{value}'")))
+            };
+            Some(SheetSprite {
+                tilesheet: sheet,
+                tilesheet_x: coordinate(x)?,
+                tilesheet_y: coordinate(y)?,
+            })
+        }
+        _ => {
+            return Err(parse_error(
+                line,
+                "expected sprite as `<name>` or `<sheet> <x> <y>`",
+            ));
+        }
+    };
+
+    Ok((glyph, Glyph { kind, sprite }))
+}
+
+pub fn parse_ascii_level(
+    text: &str,
+    catalog: &SpriteCatalog,
+    sheets: &TileSheets,
+) -> Result<LevelAsset, AsciiLevelError> {
+    let mut legend = default_legend(catalog);
+    let mut rows: Vec<(usize, &str)> = Vec::new();
+    let mut in_legend = false;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        match raw.trim() {
+            "[legend]" => in_legend = true,
+            "[map]" => in_legend = false,
+            entry if in_legend => {
+                if !entry.is_empty() {
+                    let (glyph, definition) = parse_legend_entry(line, entry, catalog, sheets)?;
+                    legend.insert(glyph, definition);
+                }
+            }
+            _ => rows.push((line, raw.trim_end())),
+        }
+    }
+
+    // Ignore blank lines around the map itself
+    while rows.first().is_some_and(|(_, row)| row.is_empty()) {
+        rows.remove(0);
+    }
+    while rows.last().is_some_and(|(_, row)| row.is_empty()) {
+        rows.pop();
+    }
+    if rows.is_empty() {
+        return Err(parse_error(text.lines().count(), "level has no map rows"));
+    }
+
+    let size = MapSize {
+        width: rows
+            .iter()
+            .map(|(_, row)| row.chars().count())
+            .max()
+            .unwrap(),
+        height: rows.len(),
+    };
+    // Spawn glyphs stand on the '.' floor, or whichever floor glyph sorts first
+    let floor = legend
+        .get(&'.')
+        .filter(|glyph| glyph.kind == GlyphKind::Floor)
+        .or_else(|| {
+            legend
+                .iter()
+                .filter(|(_, glyph)| glyph.kind == GlyphKind::Floor)
+                .min_by_key(|(c, _)| **c)
+                .map(|(_, glyph)| glyph)
+        })
+        .and_then(|glyph| glyph.sprite.clone());
+
+    let mut level = LevelAsset {
+        size,
+        tiles: Vec::new(),
+        player_spawn: None,
+        monsters: Vec::new(),
+        movement: None,
+    };
+    for (row_index, (line, row)) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            let glyph = legend
+                .get(&c)
+                .ok_or_else(|| parse_error(*line, format!("glyph '{c}' is not in the legend")))?;
+            // The first row is the top of the map, while map y grows upwards
+            let position = MapPosition {
+                x,
+                y: size.height - 1 - row_index,
+            };
+            let tile_sprite = match glyph.kind {
+                GlyphKind::Player | GlyphKind::Monster(_) => floor.clone(),
+                _ => glyph.sprite.clone(),
+            };
+            let tile = |layer, walk_cost, blocking, door| {
+                tile_sprite.clone().map(|sprite| LevelTile {
+                    position,
+                    layer,
+                    sprite,
+                    walk_cost,
+                    blocking,
+                    door,
+                })
+            };
+
+            let tile = match glyph.kind {
+                GlyphKind::Empty => None,
+                GlyphKind::Floor => tile(0, Some(1), false, false),
+                GlyphKind::Wall => tile(1, None, true, false),
+                GlyphKind::Door => tile(0, Some(1), false, true),
+                GlyphKind::Player => {
+                    if level.player_spawn.replace(position).is_some() {
+                        return Err(parse_error(*line, "more than one player spawn"));
+                    }
+                    tile(0, Some(1), false, false)
+                }
+                GlyphKind::Monster(kind) => {
+                    level.monsters.push(LevelMonster {
+                        position,
+                        kind,
+                        sprite: glyph.sprite.clone(),
+                    });
+                    tile(0, Some(1), false, false)
+                }
+            };
+            if glyph.kind != GlyphKind::Empty && tile.is_none() {
+                return Err(parse_error(*line, format!("glyph '{c}' has no sprite")));
+            }
+            level.tiles.extend(tile);
+            // Walls stand on the floor, as they do in generated maps
+            if glyph.kind == GlyphKind::Wall
+                && let Some(sprite) = floor.clone()
+            {
+                level.tiles.push(LevelTile {
+                    position,
+                    layer: 0,
+                    sprite,
+                    walk_cost: Some(1),
+                    blocking: false,
+                    door: false,
+                });
+            }
+        }
+    }
+
+    Ok(level)
+}
+
+/// Loads text levels, naming their sprites from the sprite catalog and
+/// checking the sheets they name against the tilesheet manifest.
+pub struct AsciiLevelLoader {
+    catalog: SpriteCatalog,
+    sheets: TileSheets,
+}
+
+impl FromWorld for AsciiLevelLoader {
+    fn from_world(world: &mut World) -> Self {
+        AsciiLevelLoader {
+            catalog: world
+                .get_resource::<SpriteCatalog>()
+                .cloned()
+                .unwrap_or_default(),
+            sheets: world
+                .get_resource::<TileSheets>()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl AssetLoader for AsciiLevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = AsciiLevelError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<LevelAsset, AsciiLevelError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(|err| parse_error(0, err.to_string()))?;
+        parse_ascii_level(&text, &self.catalog, &self.sheets)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<LevelAsset, AsciiLevelError> {
+        let catalog = include_str!("../../../assets/tilesheets/sprites.ron");
+        let sheets = include_str!("../../../assets/tilesheets/sheets.ron");
+        parse_ascii_level(
+            text,
+            &SpriteCatalog::parse(catalog).unwrap(),
+            &TileSheets::parse(sheets).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parses_map_with_top_row_first() {
+        let level = parse("###\n#@#\n#M#\n###\n").unwrap();
+        assert_eq!(level.size.width, 3);
+        assert_eq!(level.size.height, 4);
+        assert_eq!(level.player_spawn, Some(MapPosition { x: 1, y: 2 }));
+        assert_eq!(level.monsters[0].position, MapPosition { x: 1, y: 1 });
+        assert_eq!(level.monsters[0].kind, MonsterKind::Goblin);
+        assert_eq!(level.tiles.iter().filter(|t| t.blocking).count(), 10);
+        assert_eq!(level.tiles.iter().filter(|t| t.layer == 0).count(), 12);
+    }
+
+    #[test]
+    fn legend_overrides_sprites() {
+        let level = parse("[legend]\n# = wall world 1 2\n[map]\n#\n").unwrap();
+        let wall = &level.tiles[0];
+        assert!(wall.blocking);
+        assert_eq!((wall.sprite.tilesheet_x, wall.sprite.tilesheet_y), (1, 2));
+    }
+
+    #[test]
+    fn legend_sheets_must_be_in_the_manifest() {
+        let err = parse("[legend]\n# = wall ceiling 1 2\n[map]\n#\n").unwrap_err();
+        assert!(err.to_string().contains("unknown tilesheet 'ceiling'"));
+        assert!(matches!(err, AsciiLevelError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn legend_names_sprites_from_the_catalog() {
+        let level = parse("[legend]\n# = wall monster_rat\n[map]\n#\n").unwrap();
+        assert_eq!(level.tiles[0].sprite.tilesheet, SheetId::new("monsters"));
+        assert_eq!(level.tiles[0].sprite.tilesheet_x, 14);
+        let err = parse("[legend]\n# = wall wall_glass\n[map]\n#\n").unwrap_err();
+        assert!(matches!(err, AsciiLevelError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn legend_monsters_name_their_kind() {
+        let level =
+            parse("[legend]\nr = monster rat\nS = monster skeleton monsters 6 0\n[map]\nrS\n")
+                .unwrap();
+        assert_eq!(level.monsters[0].kind, MonsterKind::Rat);
+        assert!(level.monsters[0].sprite.is_none());
+        assert_eq!(level.monsters[1].kind, MonsterKind::Skeleton);
+        let sprite = level.monsters[1].sprite.as_ref().unwrap();
+        assert_eq!((sprite.tilesheet_x, sprite.tilesheet_y), (6, 0));
+
+        let err = parse("[legend]\nd = monster dragon\n[map]\nd\n").unwrap_err();
+        assert!(err.to_string().contains("unknown monster kind 'dragon'"));
+    }
+
+    #[test]
+    fn unknown_glyph_is_an_error() {
+        let err = parse("#?#\n").unwrap_err();
+        assert!(matches!(err, AsciiLevelError::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn example_level_parses() {
+        let level = parse(include_str!("../../../assets/levels/example.level"));
+        assert!(level.unwrap().player_spawn.is_some());
+    }
+}
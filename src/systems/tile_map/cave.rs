@@ -0,0 +1,163 @@
+use crate::{
+    components::tiles::MapPosition,
+    systems::tile_map::{
+        generation::GeneratedMap,
+        grid::{MapSize, TileKind, TileMap},
+    },
+};
+use rand::Rng;
+
+#[derive(Debug, Clone)]
+pub struct CaveParams {
+    pub size: MapSize,
+    /// Chance for each tile to start out as wall.
+    pub fill_ratio: f64,
+    pub smoothing_iterations: usize,
+    /// A floor tile with more walls than this around it turns into wall.
+    pub birth_limit: usize,
+    /// A wall tile with fewer walls than this around it turns into floor.
+    pub death_limit: usize,
+}
+
+impl Default for CaveParams {
+    fn default() -> Self {
+        CaveParams {
+            size: MapSize {
+                width: 32,
+                height: 31,
+            },
+            fill_ratio: 0.40,
+            smoothing_iterations: 5,
+            birth_limit: 4,
+            death_limit: 3,
+        }
+    }
+}
+
+pub fn generate_cave(params: &CaveParams, rng: &mut impl Rng) -> GeneratedMap {
+    let size = params.size;
+    // `random_bool` panics outside 0..=1, and NaN would slip through a clamp
+    let fill_ratio = if params.fill_ratio.is_nan() {
+        CaveParams::default().fill_ratio
+    } else {
+        params.fill_ratio.clamp(0.0, 1.0)
+    };
+    let on_border = |pos: MapPosition| {
+        pos.x == 0 || pos.y == 0 || pos.x + 1 >= size.width || pos.y + 1 >= size.height
+    };
+
+    let mut tiles = TileMap::new(size);
+    for pos in tiles.positions() {
+        if on_border(pos) || rng.random_bool(fill_ratio) {
+            tiles.set_kind(pos, TileKind::Wall);
+        } else {
+            tiles.set_kind(pos, TileKind::Floor);
+        }
+    }
+
+    for _ in 0..params.smoothing_iterations {
+        let mut next = tiles.clone();
+        for pos in tiles.positions() {
+            if on_border(pos) {
+                continue;
+            }
+            let walls = tiles
+                .surrounding(pos)
+                .filter(|&n| tiles.kind(n) == TileKind::Wall)
+                .count();
+            let kind = match tiles.kind(pos) {
+                TileKind::Wall if walls < params.death_limit => TileKind::Floor,
+                TileKind::Floor if walls > params.birth_limit => TileKind::Wall,
+                kind => kind,
+            };
+            next.set_kind(pos, kind);
+        }
+        tiles = next;
+    }
+
+    let player_spawn = largest_region_spawn(&tiles).unwrap_or_else(|| {
+        // Everything filled in; open up a single tile so the player has somewhere to stand
+        let center = MapPosition {
+            x: size.width / 2,
+            y: size.height / 2,
+        };
+        tiles.set_kind(center, TileKind::Floor);
+        center
+    });
+    tiles.fill_unreachable(player_spawn);
+    tiles.trim_hidden_walls();
+
+    GeneratedMap {
+        tiles,
+        player_spawn,
+    }
+}
+
+// Finds the biggest connected pocket of floor and returns the tile in it
+// closest to the middle of the map.
+fn largest_region_spawn(tiles: &TileMap) -> Option<MapPosition> {
+    let size = tiles.size();
+    let center = (size.width as isize / 2, size.height as isize / 2);
+    let distance =
+        |pos: MapPosition| (pos.x as isize - center.0).pow(2) + (pos.y as isize - center.1).pow(2);
+
+    let mut visited = vec![false; size.width * size.height];
+    let mut best: Option<(usize, MapPosition)> = None;
+    for pos in tiles.positions() {
+        let i = tiles.index(pos).unwrap();
+        if visited[i] || tiles.walk_cost(pos).is_none() {
+            continue;
+        }
+
+        let region = tiles.reachable_from(pos);
+        let mut count = 0;
+        let mut closest = pos;
+        for (j, reached) in region.iter().enumerate() {
+            if *reached {
+                visited[j] = true;
+                count += 1;
+                let tile = MapPosition {
+                    x: j % size.width,
+                    y: j / size.width,
+                };
+                if distance(tile) < distance(closest) {
+                    closest = tile;
+                }
+            }
+        }
+        if best.is_none_or(|(best_count, _)| count > best_count) {
+            best = Some((count, closest));
+        }
+    }
+    best.map(|(_, spawn)| spawn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::tile_map::generation::MapSeed;
+
+    #[test]
+    fn disconnected_pockets_are_culled() {
+        for seed in 0..20 {
+            let generated = generate_cave(&CaveParams::default(), &mut MapSeed(seed).rng());
+            let reachable = generated.tiles.reachable_from(generated.player_spawn);
+            for pos in generated.tiles.positions() {
+                if generated.tiles.walk_cost(pos).is_some() {
+                    assert!(reachable[generated.tiles.index(pos).unwrap()]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_fill_ratios_are_tolerated() {
+        for fill_ratio in [f64::NAN, -1.0, 2.0] {
+            let params = CaveParams {
+                fill_ratio,
+                ..CaveParams::default()
+            };
+            generate_cave(&params, &mut MapSeed(7).rng());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use crate::{
+    asset_manager::{SheetId, TileAssets},
+    components::{
+        basic::Visible,
+        tiles::{Blocking, Highlight, Layer, MapPosition, SheetSprite, Walkable},
+    },
+    systems::{fov::is_shown, game_input::cursor::HoveredTile},
+    tile_material::{TAG_HIGHLIGHTED, TAG_REMEMBERED},
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    picking::backend::HitData,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use std::collections::{HashMap, HashSet};
+
+/// Tiles per side of a terrain chunk.
+pub const CHUNK_SIZE: usize = 16;
+
+/// Which chunk `pos` falls into.
+pub fn chunk_of(pos: MapPosition) -> UVec2 {
+    UVec2::new((pos.x / CHUNK_SIZE) as u32, (pos.y / CHUNK_SIZE) as u32)
+}
+
+/// The terrain of one chunk that is drawn from one tilesheet, baked into a
+/// single mesh.
+#[derive(Component, Debug)]
+pub struct MapChunk {
+    pub coord: UVec2,
+}
+
+/// Which terrain tiles sit in which chunk, the entities drawing each chunk and
+/// the chunks that need rebuilding.
+#[derive(Resource, Default)]
+pub struct TerrainChunks {
+    tiles: HashMap<UVec2, HashSet<Entity>>,
+    chunk_of: HashMap<Entity, UVec2>,
+    meshes: HashMap<(UVec2, SheetId), Entity>,
+    dirty: HashSet<UVec2>,
+}
+
+impl TerrainChunks {
+    fn place(&mut self, entity: Entity, coord: UVec2) {
+        if let Some(old) = self.chunk_of.insert(entity, coord)
+            && let Some(tiles) = self.tiles.get_mut(&old)
+        {
+            tiles.remove(&entity);
+            self.dirty.insert(old);
+        }
+        self.tiles.entry(coord).or_default().insert(entity);
+        self.dirty.insert(coord);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(old) = self.chunk_of.remove(&entity)
+            && let Some(tiles) = self.tiles.get_mut(&old)
+        {
+            tiles.remove(&entity);
+            self.dirty.insert(old);
+        }
+    }
+
+    fn touch(&mut self, entity: Entity) {
+        if let Some(coord) = self.chunk_of.get(&entity) {
+            self.dirty.insert(*coord);
+        }
+    }
+}
+
+type Terrain = (With<SheetSprite>, Or<(With<Walkable>, With<Blocking>)>);
+type TerrainMoved = Or<(Added<SheetSprite>, Changed<MapPosition>)>;
+type TerrainRestyled = Or<(Changed<SheetSprite>, Changed<Visible>, Added<Highlight>)>;
+
+/// Files terrain tiles into chunks and marks a chunk dirty whenever one of its
+/// tiles is added, moved, removed or changes how it looks.
+#[allow(clippy::too_many_arguments)]
+pub fn track_terrain_chunks(
+    mut chunks: ResMut<TerrainChunks>,
+    moved: Query<(Entity, &MapPosition), (Terrain, TerrainMoved)>,
+    restyled: Query<Entity, (Terrain, TerrainRestyled)>,
+    terrain: Query<&MapPosition, Terrain>,
+    mut removed_sprites: RemovedComponents<SheetSprite>,
+    mut removed_walkable: RemovedComponents<Walkable>,
+    mut removed_blocking: RemovedComponents<Blocking>,
+    mut removed_highlights: RemovedComponents<Highlight>,
+) {
+    for (entity, pos) in moved.iter() {
+        chunks.place(entity, chunk_of(*pos));
+    }
+    for entity in restyled.iter() {
+        chunks.touch(entity);
+    }
+    // A tile losing one of its components may have stopped being terrain
+    let removed: Vec<Entity> = removed_sprites
+        .read()
+        .chain(removed_walkable.read())
+        .chain(removed_blocking.read())
+        .collect();
+    for entity in removed {
+        chunks.remove(entity);
+        if let Ok(pos) = terrain.get(entity) {
+            chunks.place(entity, chunk_of(*pos));
+        }
+    }
+    for entity in removed_highlights.read() {
+        chunks.touch(entity);
+    }
+}
+
+type ChunkTile<'a> = (
+    &'a MapPosition,
+    &'a SheetSprite,
+    Option<&'a Layer>,
+    &'a Visible,
+    Has<Highlight>,
+);
+
+/// Rebuilds the meshes of every dirty chunk, one per tilesheet its tiles use.
+pub fn rebuild_terrain_chunks(
+    mut commands: Commands,
+    mut chunks: ResMut<TerrainChunks>,
+    tile_assets: Option<Res<TileAssets>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tiles: Query<ChunkTile>,
+) {
+    // Chunks stay dirty until the sheets have loaded
+    let Some(tile_assets) = tile_assets else {
+        return;
+    };
+    let dirty: Vec<UVec2> = chunks.dirty.drain().collect();
+
+    for coord in dirty {
+        let mut builders: HashMap<SheetId, ChunkMeshBuilder> = HashMap::new();
+        let entities = chunks.tiles.get(&coord).into_iter().flatten();
+        for (pos, sprite, layer, visible, highlighted) in
+            entities.filter_map(|e| tiles.get(*e).ok())
+        {
+            let Some(uv) = tile_assets.uv_rect(sprite) else {
+                continue;
+            };
+            if !is_shown(*visible, true) {
+                continue;
+            }
+            let mut flags = 0;
+            if *visible == Visible::Remembered {
+                flags |= TAG_REMEMBERED;
+            }
+            if highlighted {
+                flags |= TAG_HIGHLIGHTED;
+            }
+            let center = Vec3::new(
+                pos.x as f32,
+                pos.y as f32,
+                layer.map_or(0.0, |l| l.0 as f32),
+            );
+            builders
+                .entry(sprite.tilesheet.clone())
+                .or_default()
+                .push_tile(center, uv, flags);
+        }
+
+        let stale: Vec<_> = chunks
+            .meshes
+            .keys()
+            .filter(|(c, sheet)| *c == coord && !builders.contains_key(sheet))
+            .cloned()
+            .collect();
+        for key in stale {
+            if let Some(entity) = chunks.meshes.remove(&key) {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        for (sheet, builder) in builders {
+            let mesh = Mesh3d(meshes.add(builder.build()));
+            if let Some(entity) = chunks.meshes.get(&(coord, sheet.clone())) {
+                commands.entity(*entity).insert(mesh);
+                continue;
+            }
+            let Some(material) = tile_assets.material(&sheet) else {
+                continue;
+            };
+            let entity = commands
+                .spawn((
+                    MapChunk { coord },
+                    mesh,
+                    MeshMaterial3d(material),
+                    Transform::default(),
+                ))
+                .observe(hover_chunk_over)
+                .observe(hover_chunk_move)
+                .observe(leave_chunk)
+                .id();
+            chunks.meshes.insert((coord, sheet), entity);
+        }
+    }
+}
+
+/// The tile whose cube was hit, found by stepping from the hit point back
+/// into the cube.
+fn hit_tile(hit: &HitData) -> Option<MapPosition> {
+    let inside = hit.position? - hit.normal? * 0.5;
+    let (x, y) = (inside.x.round(), inside.y.round());
+    (x >= 0.0 && y >= 0.0).then_some(MapPosition {
+        x: x as usize,
+        y: y as usize,
+    })
+}
+
+fn hover_chunk_over(over: Trigger<Pointer<Over>>, mut hovered: ResMut<HoveredTile>) {
+    if let Some(pos) = hit_tile(&over.hit) {
+        hovered.set_if_neq(HoveredTile(Some(pos)));
+    }
+}
+
+fn hover_chunk_move(moved: Trigger<Pointer<Move>>, mut hovered: ResMut<HoveredTile>) {
+    if let Some(pos) = hit_tile(&moved.hit) {
+        hovered.set_if_neq(HoveredTile(Some(pos)));
+    }
+}
+
+fn leave_chunk(
+    out: Trigger<Pointer<Out>>,
+    chunks: Query<&MapChunk>,
+    mut hovered: ResMut<HoveredTile>,
+) {
+    let Ok(chunk) = chunks.get(out.target()) else {
+        return;
+    };
+    if hovered.0.is_some_and(|pos| chunk_of(pos) == chunk.coord) {
+        hovered.0 = None;
+    }
+}
+
+/// Sides of a tile cube as (normal, right, up), seen from outside. The bottom
+/// is never in view and left out.
+const FACES: [(Vec3, Vec3, Vec3); 5] = [
+    (Vec3::Z, Vec3::X, Vec3::Y),
+    (Vec3::X, Vec3::Y, Vec3::Z),
+    (Vec3::NEG_X, Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Y, Vec3::NEG_X, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+];
+
+/// Collects the tile cubes of one chunk into a single mesh. Each vertex
+/// carries its atlas UV, and the tile's fog and highlight flags in its color.
+#[derive(Default)]
+struct ChunkMeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl ChunkMeshBuilder {
+    fn push_tile(&mut self, center: Vec3, uv: Rect, flags: u32) {
+        let color = [
+            (flags & TAG_REMEMBERED != 0) as u8 as f32,
+            (flags & TAG_HIGHLIGHTED != 0) as u8 as f32,
+            0.0,
+            1.0,
+        ];
+
+        for (normal, right, up) in FACES {
+            let first = self.positions.len() as u32;
+            for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let corner = center + (normal + right * dx + up * dy) * 0.5;
+                self.positions.push(corner.into());
+                self.normals.push(normal.into());
+                // Texture rows run top to bottom
+                let fraction = Vec2::new((dx + 1.0) / 2.0, (1.0 - dy) / 2.0);
+                self.uvs.push((uv.min + fraction * uv.size()).into());
+                self.colors.push(color);
+            }
+            self.indices
+                .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    fn build(self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_cover_whole_blocks_of_tiles() {
+        assert_eq!(chunk_of(MapPosition { x: 0, y: 15 }), UVec2::new(0, 0));
+        assert_eq!(chunk_of(MapPosition { x: 16, y: 15 }), UVec2::new(1, 0));
+        assert_eq!(chunk_of(MapPosition { x: 40, y: 33 }), UVec2::new(2, 2));
+    }
+
+    #[test]
+    fn tiles_are_textured_from_their_cell() {
+        let cell = Rect::new(0.25, 0.5, 0.5, 0.75);
+        let mut builder = ChunkMeshBuilder::default();
+        builder.push_tile(Vec3::new(3.0, 4.0, 1.0), cell, TAG_REMEMBERED);
+
+        assert_eq!(builder.positions.len(), 20);
+        assert_eq!(builder.indices.len(), 30);
+        assert!(
+            builder
+                .uvs
+                .iter()
+                .all(|&[u, v]| cell.contains(Vec2::new(u, v)))
+        );
+        assert!(builder.colors.iter().all(|c| *c == [1.0, 0.0, 0.0, 1.0]));
+        // The top face spans the tile at the top of the cube
+        assert_eq!(builder.positions[0], [2.5, 3.5, 1.5]);
+        assert_eq!(builder.positions[2], [3.5, 4.5, 1.5]);
+    }
+
+    #[test]
+    fn hits_map_back_to_their_tile() {
+        let hit =
+            |position, normal| HitData::new(Entity::PLACEHOLDER, 1.0, Some(position), Some(normal));
+        let top = hit(Vec3::new(3.2, 4.4, 1.5), Vec3::Z);
+        assert_eq!(hit_tile(&top), Some(MapPosition { x: 3, y: 4 }));
+        let side = hit(Vec3::new(3.5, 4.1, 0.8), Vec3::X);
+        assert_eq!(hit_tile(&side), Some(MapPosition { x: 3, y: 4 }));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    components::tiles::MapPosition,
+    systems::tile_map::{
+        generation::GeneratedMap,
+        grid::{MapSize, TileKind, TileMap},
+    },
+};
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Room {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Room {
+    pub fn center(&self) -> MapPosition {
+        MapPosition {
+            x: self.x + self.width / 2,
+            y: self.y + self.height / 2,
+        }
+    }
+
+    pub fn contains(&self, pos: MapPosition) -> bool {
+        pos.x >= self.x
+            && pos.x < self.x + self.width
+            && pos.y >= self.y
+            && pos.y < self.y + self.height
+    }
+
+    // Rooms keep at least one tile of wall between each other
+    fn overlaps(&self, other: &Room) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DungeonParams {
+    pub size: MapSize,
+    pub max_rooms: usize,
+    pub room_min: usize,
+    pub room_max: usize,
+}
+
+impl Default for DungeonParams {
+    fn default() -> Self {
+        DungeonParams {
+            size: MapSize {
+                width: 32,
+                height: 31,
+            },
+            max_rooms: 12,
+            room_min: 4,
+            room_max: 9,
+        }
+    }
+}
+
+pub fn generate_dungeon(params: &DungeonParams, rng: &mut impl Rng) -> GeneratedMap {
+    let size = params.size;
+    let mut tiles = TileMap::new(size);
+    for pos in tiles.positions() {
+        tiles.set_kind(pos, TileKind::Wall);
+    }
+
+    let mut rooms: Vec<Room> = Vec::new();
+    let room_min = params.room_min.max(1);
+    let room_max = params.room_max.max(room_min);
+    for _ in 0..params.max_rooms {
+        // Leave the outermost ring of the map as wall
+        let max_width = room_max.min(size.width.saturating_sub(2));
+        let max_height = room_max.min(size.height.saturating_sub(2));
+        if max_width < room_min || max_height < room_min {
+            break;
+        }
+        let width = rng.random_range(room_min..=max_width);
+        let height = rng.random_range(room_min..=max_height);
+        let room = Room {
+            x: rng.random_range(1..=size.width - width - 1),
+            y: rng.random_range(1..=size.height - height - 1),
+            width,
+            height,
+        };
+        if rooms.iter().any(|other| room.overlaps(other)) {
+            continue;
+        }
+
+        carve_room(&mut tiles, &room);
+        if let Some(previous) = rooms.last() {
+            carve_corridor(
+                &mut tiles,
+                previous.center(),
+                room.center(),
+                rng.random_bool(0.5),
+            );
+        }
+        rooms.push(room);
+    }
+
+    if rooms.is_empty() {
+        let room = Room {
+            x: 1.min(size.width.saturating_sub(1)),
+            y: 1.min(size.height.saturating_sub(1)),
+            width: size.width.saturating_sub(2).max(1),
+            height: size.height.saturating_sub(2).max(1),
+        };
+        carve_room(&mut tiles, &room);
+        rooms.push(room);
+    }
+
+    let player_spawn = rooms[0].center();
+    place_doors(&mut tiles, &rooms);
+    tiles.fill_unreachable(player_spawn);
+    tiles.trim_hidden_walls();
+
+    GeneratedMap {
+        tiles,
+        player_spawn,
+    }
+}
+
+fn carve_room(tiles: &mut TileMap, room: &Room) {
+    for y in room.y..room.y + room.height {
+        for x in room.x..room.x + room.width {
+            tiles.set_kind(MapPosition { x, y }, TileKind::Floor);
+        }
+    }
+}
+
+fn carve_corridor(tiles: &mut TileMap, from: MapPosition, to: MapPosition, horizontal_first: bool) {
+    let corner = if horizontal_first {
+        MapPosition { x: to.x, y: from.y }
+    } else {
+        MapPosition { x: from.x, y: to.y }
+    };
+    for (a, b) in [(from, corner), (corner, to)] {
+        for x in a.x.min(b.x)..=a.x.max(b.x) {
+            for y in a.y.min(b.y)..=a.y.max(b.y) {
+                tiles.set_kind(MapPosition { x, y }, TileKind::Floor);
+            }
+        }
+    }
+}
+
+// A door goes where a corridor pierces the wall ring around a room and is
+// flanked by wall on both sides.
+fn place_doors(tiles: &mut TileMap, rooms: &[Room]) {
+    for room in rooms {
+        let left = room.x.saturating_sub(1);
+        let right = room.x + room.width;
+        let bottom = room.y.saturating_sub(1);
+        let top = room.y + room.height;
+
+        let horizontal = (room.x..right).flat_map(|x| {
+            [
+                (MapPosition { x, y: bottom }, true),
+                (MapPosition { x, y: top }, true),
+            ]
+        });
+        let vertical = (room.y..top).flat_map(|y| {
+            [
+                (MapPosition { x: left, y }, false),
+                (MapPosition { x: right, y }, false),
+            ]
+        });
+
+        for (pos, on_horizontal_edge) in horizontal.chain(vertical).collect::<Vec<_>>() {
+            if tiles.kind(pos) != TileKind::Floor {
+                continue;
+            }
+            let (a, b) = if on_horizontal_edge {
+                (
+                    pos.x.checked_sub(1).map(|x| MapPosition { x, y: pos.y }),
+                    Some(MapPosition {
+                        x: pos.x + 1,
+                        y: pos.y,
+                    }),
+                )
+            } else {
+                (
+                    pos.y.checked_sub(1).map(|y| MapPosition { x: pos.x, y }),
+                    Some(MapPosition {
+                        x: pos.x,
+                        y: pos.y + 1,
+                    }),
+                )
+            };
+            let flanked = [a, b]
+                .into_iter()
+                .all(|p| p.is_some_and(|p| tiles.kind(p) == TileKind::Wall));
+            let inside_other_room = rooms.iter().any(|r| r.contains(pos));
+            if flanked && !inside_other_room {
+                tiles.set_kind(pos, TileKind::Door);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::tile_map::generation::MapSeed;
+
+    // FNV-1a over the tile kinds and the spawn, which unlike `DefaultHasher`
+    // gives the same value on every platform and Rust version
+    fn layout_hash(seed: u64) -> u64 {
+        let generated = generate_dungeon(&DungeonParams::default(), &mut MapSeed(seed).rng());
+        let tiles = &generated.tiles;
+        let spawn = generated.player_spawn;
+        tiles
+            .positions()
+            .map(|pos| tiles.kind(pos) as u64)
+            .chain([spawn.x as u64, spawn.y as u64])
+            .fold(0xcbf2_9ce4_8422_2325, |hash, value| {
+                (hash ^ value).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    #[test]
+    fn same_seed_produces_identical_layout() {
+        assert_eq!(layout_hash(1234), 0x3e4a_64a3_765a_5b51);
+        assert_eq!(layout_hash(0), 0xb253_6a37_6b94_78db);
+    }
+
+    #[test]
+    fn different_seeds_produce_different_layouts() {
+        assert_ne!(layout_hash(1234), layout_hash(4321));
+    }
+
+    #[test]
+    fn every_floor_tile_is_reachable_from_spawn() {
+        for seed in 0..20 {
+            let generated = generate_dungeon(&DungeonParams::default(), &mut MapSeed(seed).rng());
+            let reachable = generated.tiles.reachable_from(generated.player_spawn);
+            for pos in generated.tiles.positions() {
+                if generated.tiles.walk_cost(pos).is_some() {
+                    assert!(reachable[generated.tiles.index(pos).unwrap()]);
+                }
+            }
+        }
+    }
+}
@@ -1,61 +1,289 @@
 use crate::{
-    asset_manager::TileSheetType,
     components::{basic::*, tiles::*},
-    entities::{FloorTileBundle, WallTileBundle},
-    events::HighlightEvent,
+    entities::{FloorTileBundle, MonsterKind, PlayerBundle, WallTileBundle},
+    sprite_catalog::{SpriteCatalog, names},
+    systems::pathfinding::{Movement, astar::manhattan_distance},
+    systems::tile_map::{
+        cave::{CaveParams, generate_cave},
+        dungeon::{DungeonParams, generate_dungeon},
+        grid::{TileKind, TileMap},
+        level::{LevelAsset, PendingLevel},
+    },
 };
 use bevy::prelude::*;
-use rand::Rng;
+use rand::{Rng, SeedableRng, seq::IndexedRandom};
+use rand_chacha::ChaCha8Rng;
 
-pub struct MapSize {
-    width: usize,
-    height: usize,
+/// Output of a map generator, before anything is spawned into the world.
+#[derive(Debug, Clone)]
+pub struct GeneratedMap {
+    pub tiles: TileMap,
+    pub player_spawn: MapPosition,
 }
 
-pub fn generate_test_map(mut commands: Commands) {
-    let map = MapSize {
-        width: 32,
-        height: 31,
+/// Which generator builds the level, along with its parameters.
+#[derive(Resource, Debug, Clone)]
+pub enum MapGenerator {
+    Dungeon(DungeonParams),
+    Cave(CaveParams),
+}
+
+impl Default for MapGenerator {
+    fn default() -> Self {
+        MapGenerator::Dungeon(DungeonParams::default())
+    }
+}
+
+impl MapGenerator {
+    /// Picks the generator from `--map-style <dungeon|cave>`.
+    pub fn from_args() -> Self {
+        match arg_value("--map-style").as_deref() {
+            Some("cave") => MapGenerator::Cave(CaveParams::default()),
+            Some("dungeon") | None => MapGenerator::Dungeon(DungeonParams::default()),
+            Some(other) => {
+                warn!("Unknown map style '{other}', using dungeon");
+                MapGenerator::default()
+            }
+        }
+    }
+
+    pub fn generate(&self, rng: &mut impl Rng) -> GeneratedMap {
+        match self {
+            MapGenerator::Dungeon(params) => generate_dungeon(params, rng),
+            MapGenerator::Cave(params) => generate_cave(params, rng),
+        }
+    }
+}
+
+/// Seed for every map generator. The same seed and parameters always produce
+/// the same layout, so a seed is enough to reproduce a bad map.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSeed(pub u64);
+
+impl MapSeed {
+    /// Reads `--seed <n>` from the command line, then the `DUDS_SEED`
+    /// environment variable, and falls back to a random seed.
+    pub fn from_args() -> Self {
+        let seed = arg_value("--seed").or_else(|| std::env::var("DUDS_SEED").ok());
+
+        match seed.map(|s| s.parse::<u64>()) {
+            Some(Ok(seed)) => MapSeed(seed),
+            Some(Err(err)) => {
+                warn!("Invalid map seed ({err}), using a random one");
+                MapSeed(rand::rng().random())
+            }
+            None => MapSeed(rand::rng().random()),
+        }
+    }
+
+    /// ChaCha is used rather than `StdRng`, whose output may change between
+    /// rand versions and platforms.
+    pub fn rng(&self) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.0)
+    }
+}
+
+// Accepts both `--name value` and `--name=value`
+pub(crate) fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    let mut value = None;
+    while let Some(arg) = args.next() {
+        if arg == name {
+            value = args.next();
+        } else if let Some(v) = arg.strip_prefix(name).and_then(|v| v.strip_prefix('=')) {
+            value = Some(v.to_string());
+        }
+    }
+    value
+}
+
+impl PendingLevel {
+    /// Starts loading the level named by `--level <path>`, if any.
+    pub fn from_args(asset_server: &AssetServer) -> Option<Self> {
+        arg_value("--level").map(|path| PendingLevel(asset_server.load(path)))
+    }
+}
+
+pub fn generate_level(
+    commands: &mut Commands,
+    generator: &MapGenerator,
+    seed: MapSeed,
+    catalog: &SpriteCatalog,
+) {
+    info!("Generating {:?} with seed {}", generator, seed.0);
+    let mut rng = seed.rng();
+    let generated = generator.generate(&mut rng);
+    for (position, kind) in place_monsters(&generated, &mut rng) {
+        commands.spawn(kind.bundle(catalog, position));
+    }
+    spawn_tile_map(commands, generated.tiles, &mut rng, catalog);
+    commands.spawn(PlayerBundle::new(catalog, generated.player_spawn));
+}
+
+// One monster for every this many floor tiles
+const FLOOR_TILES_PER_MONSTER: usize = 40;
+// Keeps monsters from spawning right next to the player
+const MONSTER_SPAWN_DISTANCE: u32 = 6;
+
+fn place_monsters(generated: &GeneratedMap, rng: &mut impl Rng) -> Vec<(MapPosition, MonsterKind)> {
+    let tiles = &generated.tiles;
+    let floor_count = tiles
+        .positions()
+        .filter(|&pos| tiles.kind(pos) == TileKind::Floor)
+        .count();
+    let candidates: Vec<MapPosition> = tiles
+        .positions()
+        .filter(|&pos| {
+            tiles.kind(pos) == TileKind::Floor
+                && manhattan_distance(pos, generated.player_spawn) >= MONSTER_SPAWN_DISTANCE
+        })
+        .collect();
+
+    candidates
+        .choose_multiple(rng, floor_count / FLOOR_TILES_PER_MONSTER)
+        .map(|&pos| (pos, *MonsterKind::ALL.choose(rng).unwrap()))
+        .collect()
+}
+
+pub fn spawn_pending_level(
+    mut commands: Commands,
+    pending: Option<Res<PendingLevel>>,
+    levels: Res<Assets<LevelAsset>>,
+    catalog: Res<SpriteCatalog>,
+) {
+    let Some(pending) = pending else {
+        return;
     };
-    let mut rng = rand::rng();
-    for x in 0..map.width {
-        for y in 0..map.height {
-            commands
-                .spawn((
+    let Some(level) = levels.get(&pending.0) else {
+        return;
+    };
+    spawn_level_asset(&mut commands, level, &catalog);
+    commands.remove_resource::<PendingLevel>();
+}
+
+pub fn spawn_level_asset(commands: &mut Commands, level: &LevelAsset, catalog: &SpriteCatalog) {
+    for tile in &level.tiles {
+        let mut entity = commands.spawn((
+            tile.sprite.clone(),
+            tile.position,
+            Layer(tile.layer),
+            Visible::default(),
+        ));
+        if let Some(cost) = tile.walk_cost {
+            entity.insert(Walkable { cost });
+        }
+        if tile.blocking {
+            entity.insert(Blocking);
+        }
+        if tile.door {
+            entity.insert(Door);
+        }
+    }
+    for monster in &level.monsters {
+        let mut bundle = monster.kind.bundle(catalog, monster.position);
+        if let Some(sprite) = &monster.sprite {
+            bundle.sheetsprite = sprite.clone();
+        }
+        commands.spawn(bundle);
+    }
+
+    let player_spawn = level.player_spawn.unwrap_or_else(|| {
+        warn!("Level has no player spawn, placing the player at the origin");
+        MapPosition::default()
+    });
+    commands.spawn(PlayerBundle::new(catalog, player_spawn));
+
+    if let Some(movement) = level.movement {
+        commands.insert_resource(Movement(movement));
+    }
+    // The walk costs get filled in by `sync_tile_map` from the spawned tiles
+    commands.insert_resource(level.size);
+    commands.insert_resource(TileMap::new(level.size));
+}
+
+/// Spawns the tile entities for `tile_map` and inserts it as the active map.
+pub fn spawn_tile_map(
+    commands: &mut Commands,
+    tile_map: TileMap,
+    rng: &mut impl Rng,
+    catalog: &SpriteCatalog,
+) {
+    for pos in tile_map.positions() {
+        match tile_map.kind(pos) {
+            TileKind::Empty => {}
+            TileKind::Floor => {
+                commands.spawn((
                     FloorTileBundle {
-                        map_position: MapPosition { x, y },
-                        sheetsprite: SheetSprite {
-                            tilesheet: TileSheetType::World,
-                            tilesheet_x: 5,
-                            tilesheet_y: rng.random_range(8..12),
-                        },
+                        map_position: pos,
+                        sheetsprite: SheetSprite::any_named(catalog, names::FLOOR, rng),
                         walkable: Walkable { cost: 1 },
                     },
-                    Visible,
-                ))
-                .observe(walkable_hover_trigger);
-            if rng.random_range(0..10) > 8 {
+                    Visible::default(),
+                ));
+            }
+            TileKind::Door => {
                 commands.spawn((
-                    WallTileBundle {
-                        map_position: MapPosition { x, y },
-                        ..default()
+                    FloorTileBundle {
+                        map_position: pos,
+                        sheetsprite: SheetSprite::named(catalog, names::DOOR),
+                        walkable: Walkable { cost: 1 },
                     },
-                    Visible,
+                    Door,
+                    Visible::default(),
+                ));
+            }
+            TileKind::Wall => {
+                commands.spawn((WallTileBundle::new(catalog, pos), Visible::default()));
+                commands.spawn((
+                    FloorTileBundle {
+                        map_position: pos,
+                        sheetsprite: SheetSprite::any_named(catalog, names::FLOOR, rng),
+                        walkable: Walkable { cost: 1 },
+                    },
+                    Visible::default(),
                 ));
             }
         }
     }
+    commands.insert_resource(tile_map.size());
+    commands.insert_resource(tile_map);
 }
-fn walkable_hover_trigger(
-    hover: Trigger<Pointer<Over>>,
-    mut ev_highlight: EventWriter<HighlightEvent>,
-    mut tiles: Query<(Entity, &mut Transform, Option<&Highlight>)>,
-) {
-    for (tile_entity, _transform, highlight) in tiles.iter_mut() {
-        if tile_entity == hover.target() && highlight.is_none() {
-            ev_highlight.write(HighlightEvent(tile_entity, true));
-        } else if tile_entity != hover.target() && highlight.is_some() {
-            ev_highlight.write(HighlightEvent(tile_entity, false));
-        }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset_manager::TileSheets, components::attributes::Energy,
+        systems::tile_map::ascii::parse_ascii_level,
+    };
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn loaded_monsters_get_their_kinds_stats() {
+        let catalog =
+            SpriteCatalog::parse(include_str!("../../../assets/tilesheets/sprites.ron")).unwrap();
+        let sheets =
+            TileSheets::parse(include_str!("../../../assets/tilesheets/sheets.ron")).unwrap();
+        let level = parse_ascii_level(
+            include_str!("../../../assets/levels/example.level"),
+            &catalog,
+            &sheets,
+        )
+        .unwrap();
+
+        let mut world = World::new();
+        world
+            .run_system_once(move |mut commands: Commands| {
+                spawn_level_asset(&mut commands, &level, &catalog)
+            })
+            .unwrap();
+        let mut speeds: Vec<u32> = world
+            .query_filtered::<&Energy, With<Monster>>()
+            .iter(&world)
+            .map(|energy| energy.speed)
+            .collect();
+        speeds.sort();
+        // A skeleton, a goblin and a rat
+        assert_eq!(speeds, vec![8, 10, 12]);
     }
 }
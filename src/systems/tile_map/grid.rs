@@ -0,0 +1,263 @@
+use crate::components::tiles::*;
+use bevy::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TileKind {
+    #[default]
+    Empty,
+    Floor,
+    Wall,
+    Door,
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MapSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MapSize {
+    pub fn contains(&self, pos: MapPosition) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(
+            self.width.saturating_sub(1) as f32 / 2.0,
+            self.height.saturating_sub(1) as f32 / 2.0,
+        )
+    }
+}
+
+/// Dense terrain grid indexed by `MapPosition`. This is the source of truth for
+/// walkability; `sync_tile_map` keeps it in line with the tile entities.
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TileMap {
+    size: MapSize,
+    kinds: Vec<TileKind>,
+    costs: Vec<Option<u32>>,
+    blocking: Vec<bool>,
+}
+
+impl TileMap {
+    pub fn new(size: MapSize) -> Self {
+        let len = size.width * size.height;
+        TileMap {
+            size,
+            kinds: vec![TileKind::Empty; len],
+            costs: vec![None; len],
+            blocking: vec![false; len],
+        }
+    }
+
+    pub fn size(&self) -> MapSize {
+        self.size
+    }
+
+    pub fn index(&self, pos: MapPosition) -> Option<usize> {
+        if self.size.contains(pos) {
+            Some(pos.y * self.size.width + pos.x)
+        } else {
+            None
+        }
+    }
+
+    pub fn kind(&self, pos: MapPosition) -> TileKind {
+        self.index(pos)
+            .map(|i| self.kinds[i])
+            .unwrap_or(TileKind::Empty)
+    }
+
+    /// Walls and solid rock block line of sight.
+    pub fn is_opaque(&self, pos: MapPosition) -> bool {
+        self.index(pos)
+            .is_none_or(|i| self.blocking[i] || self.kinds[i] == TileKind::Empty)
+    }
+
+    pub fn is_blocking(&self, pos: MapPosition) -> bool {
+        self.index(pos).is_some_and(|i| self.blocking[i])
+    }
+
+    /// Cost of stepping onto `pos`, or `None` if it can't be entered.
+    pub fn walk_cost(&self, pos: MapPosition) -> Option<u32> {
+        let i = self.index(pos)?;
+        if self.blocking[i] {
+            None
+        } else {
+            self.costs[i]
+        }
+    }
+
+    pub fn set_kind(&mut self, pos: MapPosition, kind: TileKind) {
+        if let Some(i) = self.index(pos) {
+            self.kinds[i] = kind;
+            match kind {
+                TileKind::Empty => {
+                    self.costs[i] = None;
+                    self.blocking[i] = false;
+                }
+                TileKind::Floor | TileKind::Door => {
+                    self.costs[i] = Some(1);
+                    self.blocking[i] = false;
+                }
+                TileKind::Wall => {
+                    self.costs[i] = None;
+                    self.blocking[i] = true;
+                }
+            }
+        }
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = MapPosition> + use<> {
+        let width = self.size.width;
+        (0..width * self.size.height).map(move |i| MapPosition {
+            x: i % width,
+            y: i / width,
+        })
+    }
+
+    pub fn orthogonal_neighbors(
+        &self,
+        pos: MapPosition,
+    ) -> impl Iterator<Item = MapPosition> + use<> {
+        let size = self.size;
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| {
+                let x = pos.x.checked_add_signed(dx)?;
+                let y = pos.y.checked_add_signed(dy)?;
+                let neighbor = MapPosition { x, y };
+                size.contains(neighbor).then_some(neighbor)
+            })
+    }
+
+    /// Marks every tile that can be walked to from `start`.
+    pub fn reachable_from(&self, start: MapPosition) -> Vec<bool> {
+        let mut reached = vec![false; self.kinds.len()];
+        let Some(start_index) = self.index(start) else {
+            return reached;
+        };
+        if self.walk_cost(start).is_none() {
+            return reached;
+        }
+
+        reached[start_index] = true;
+        let mut stack = vec![start];
+        while let Some(pos) = stack.pop() {
+            for neighbor in self.orthogonal_neighbors(pos) {
+                let i = self.index(neighbor).unwrap();
+                if !reached[i] && self.walk_cost(neighbor).is_some() {
+                    reached[i] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        reached
+    }
+
+    /// All eight tiles around `pos` that lie inside the map.
+    pub fn surrounding(&self, pos: MapPosition) -> impl Iterator<Item = MapPosition> + use<> {
+        let size = self.size;
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&d| d != (0, 0))
+            .filter_map(move |(dx, dy)| {
+                let neighbor = MapPosition {
+                    x: pos.x.checked_add_signed(dx)?,
+                    y: pos.y.checked_add_signed(dy)?,
+                };
+                size.contains(neighbor).then_some(neighbor)
+            })
+    }
+
+    /// Walls off every walkable tile that can't be reached from `start`.
+    pub fn fill_unreachable(&mut self, start: MapPosition) {
+        let reachable = self.reachable_from(start);
+        for pos in self.positions() {
+            let i = self.index(pos).unwrap();
+            if !reachable[i] && self.walk_cost(pos).is_some() {
+                self.set_kind(pos, TileKind::Wall);
+            }
+        }
+    }
+
+    /// Solid rock that doesn't border any walkable tile is never seen, so it
+    /// is left empty instead of spawning wall entities for it.
+    pub fn trim_hidden_walls(&mut self) {
+        let hidden: Vec<MapPosition> = self
+            .positions()
+            .filter(|&pos| {
+                self.kind(pos) == TileKind::Wall
+                    && self.surrounding(pos).all(|n| self.walk_cost(n).is_none())
+            })
+            .collect();
+        for pos in hidden {
+            self.set_kind(pos, TileKind::Empty);
+        }
+    }
+
+    fn clear_terrain(&mut self) {
+        self.kinds.fill(TileKind::Empty);
+        self.costs.fill(None);
+        self.blocking.fill(false);
+    }
+
+    fn apply_tile(
+        &mut self,
+        pos: MapPosition,
+        walkable: Option<&Walkable>,
+        blocking: bool,
+        door: bool,
+    ) {
+        if let Some(i) = self.index(pos) {
+            if let Some(w) = walkable {
+                self.costs[i] = Some(w.cost.max(1));
+                if self.kinds[i] == TileKind::Empty {
+                    self.kinds[i] = TileKind::Floor;
+                }
+            }
+            if door {
+                self.kinds[i] = TileKind::Door;
+            }
+            if blocking {
+                self.blocking[i] = true;
+                self.kinds[i] = TileKind::Wall;
+            }
+        }
+    }
+}
+
+type TerrainChanged = (
+    Or<(Changed<Walkable>, Added<Blocking>, Changed<MapPosition>)>,
+    Or<(With<Walkable>, With<Blocking>)>,
+);
+
+type TerrainTile = (
+    &'static MapPosition,
+    Option<&'static Walkable>,
+    Option<&'static Blocking>,
+    Has<Door>,
+);
+
+/// Rebuilds walk costs and blocking flags from the tile entities whenever a
+/// `Walkable` or `Blocking` entity is added, moved, changed or removed.
+pub fn sync_tile_map(
+    tile_map: Option<ResMut<TileMap>>,
+    changed: Query<(), TerrainChanged>,
+    mut removed_walkable: RemovedComponents<Walkable>,
+    mut removed_blocking: RemovedComponents<Blocking>,
+    tiles: Query<TerrainTile>,
+) {
+    let removed = removed_walkable.read().count() + removed_blocking.read().count() > 0;
+    let Some(mut tile_map) = tile_map else {
+        return;
+    };
+    if changed.is_empty() && !removed {
+        return;
+    }
+
+    tile_map.clear_terrain();
+    for (pos, walkable, blocking, door) in tiles.iter() {
+        tile_map.apply_tile(*pos, walkable, blocking.is_some(), door);
+    }
+}
@@ -1,58 +1,68 @@
 use crate::{
     components::{
+        attributes::Moving,
         basic::{PathMarker, Player},
-        tiles::{Highlight, MapPosition, Target},
+        tiles::{Highlight, MapPosition, Target, Walkable},
+    },
+    systems::{
+        game_input::cursor::{HoveredTile, PathPreview},
+        tile_map::grid::MapSize,
     },
-    events::HighlightEvent,
 };
 use bevy::{pbr::NotShadowCaster, prelude::*};
 
+/// Highlights the walkable tile under the cursor.
 pub fn highlight_changed(
     mut commands: Commands,
-    mut ev_highlight: EventReader<HighlightEvent>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    tile_query: Query<(Entity, &MapPosition, &MeshMaterial3d<StandardMaterial>)>,
-    mut player_query: Query<&mut Target, With<Player>>,
+    hovered: Res<HoveredTile>,
+    tiles: Query<(Entity, &MapPosition, Has<Highlight>), With<Walkable>>,
+    map_size: Option<Res<MapSize>>,
 ) {
-    let mut target_changed = false;
-    let mut target_map_position = None;
-    for ev in ev_highlight.read() {
-        if let Ok((entity, map_position, material_wrapper)) = tile_query.get(ev.0) {
-            if ev.1 == true {
-                commands.entity(entity).insert(Highlight);
-                target_changed = true;
-                target_map_position = Some(*map_position);
-                if let Some(material) = materials.get_mut(&material_wrapper.0) {
-                    material.emissive = Color::linear_rgb(0.3, 0.3, 0.0).into();
-                }
-            }
-            if ev.1 == false {
-                commands.entity(entity).remove::<Highlight>();
-                if let Some(material) = materials.get_mut(&material_wrapper.0) {
-                    material.emissive = Color::BLACK.into();
-                }
-            }
-        }
+    if !hovered.is_changed() {
+        return;
     }
-    if target_changed {
-        if let Ok(mut player_target) = player_query.single_mut() {
-            player_target.position = target_map_position;
+    let target = hovered
+        .0
+        .filter(|pos| map_size.as_ref().is_some_and(|size| size.contains(*pos)));
+    for (entity, map_position, highlighted) in tiles.iter() {
+        let hovered = target == Some(*map_position);
+        if hovered && !highlighted {
+            commands.entity(entity).insert(Highlight);
+        } else if !hovered && highlighted {
+            commands.entity(entity).remove::<Highlight>();
         }
     }
 }
+
+/// Marks the path the player is walking, or the one they would walk if they
+/// clicked.
 pub fn highlight_target_path(
     mut commands: Commands,
-    target_query: Query<&Target, Changed<Target>>,
+    preview: Res<PathPreview>,
+    player: Query<(Ref<Target>, Has<Moving>), With<Player>>,
     marker_query: Query<Entity, With<PathMarker>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
+    let Ok((target, moving)) = player.single() else {
+        return;
+    };
+    if !preview.is_changed() && !target.is_changed() {
+        return;
+    }
+
     // Remove old markers
-    if !target_query.is_empty() {
-        for entity in marker_query.iter() {
-            commands.entity(entity).try_despawn();
-        }
+    for entity in marker_query.iter() {
+        commands.entity(entity).try_despawn();
     }
+    let path = if moving {
+        target.path.as_ref()
+    } else {
+        preview.0.as_ref()
+    };
+    let Some(path) = path else {
+        return;
+    };
 
     // Create shared mesh and material
     let marker_mesh = meshes.add(Mesh::from(Cuboid {
@@ -66,20 +76,18 @@ pub fn highlight_target_path(
         ..default()
     });
 
-    for target in target_query.iter() {
-        if let Some(path) = &target.path {
-            for pos in path {
-                commands.spawn((
-                    Transform::from_xyz(pos.x as f32, pos.y as f32, 1.0),
-                    Visibility::Visible,
-                    MeshMaterial3d(marker_material.clone()),
-                    Mesh3d(marker_mesh.clone()),
-                    GlobalTransform::default(),
-                    InheritedVisibility::default(),
-                    PathMarker,
-                    NotShadowCaster, // Optional
-                ));
-            }
-        }
+    for pos in path {
+        commands.spawn((
+            Transform::from_xyz(pos.x as f32, pos.y as f32, 1.0),
+            Visibility::Visible,
+            MeshMaterial3d(marker_material.clone()),
+            Mesh3d(marker_mesh.clone()),
+            GlobalTransform::default(),
+            InheritedVisibility::default(),
+            PathMarker,
+            NotShadowCaster, // Optional
+            // Markers must not steal hovers from the tiles below
+            Pickable::IGNORE,
+        ));
     }
 }
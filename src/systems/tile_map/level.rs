@@ -0,0 +1,41 @@
+use crate::{
+    components::tiles::{MapPosition, SheetSprite},
+    entities::MonsterKind,
+    systems::{pathfinding::astar::MovementRules, tile_map::grid::MapSize},
+};
+use bevy::prelude::*;
+
+/// A tile entity described by a hand-authored level file.
+#[derive(Debug, Clone)]
+pub struct LevelTile {
+    pub position: MapPosition,
+    pub layer: u32,
+    pub sprite: SheetSprite,
+    pub walk_cost: Option<u32>,
+    pub blocking: bool,
+    pub door: bool,
+}
+
+/// A monster placed by a level file.
+#[derive(Debug, Clone)]
+pub struct LevelMonster {
+    pub position: MapPosition,
+    pub kind: MonsterKind,
+    /// Drawn instead of the kind's own sprite.
+    pub sprite: Option<SheetSprite>,
+}
+
+/// A level loaded from disk rather than generated.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct LevelAsset {
+    pub size: MapSize,
+    pub tiles: Vec<LevelTile>,
+    pub player_spawn: Option<MapPosition>,
+    pub monsters: Vec<LevelMonster>,
+    /// Movement rules for this map, if it doesn't use the default ones.
+    pub movement: Option<MovementRules>,
+}
+
+/// Level file requested on the command line, spawned once it has loaded.
+#[derive(Resource)]
+pub struct PendingLevel(pub Handle<LevelAsset>);
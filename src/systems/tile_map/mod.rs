@@ -1,3 +1,10 @@
+pub mod ascii;
+pub mod cave;
+pub mod chunks;
+pub mod dungeon;
 pub mod generation;
+pub mod grid;
 pub mod highlight;
+pub mod level;
+pub mod tiled;
 pub mod util;
@@ -0,0 +1,849 @@
+use crate::{
+    asset_manager::{SheetId, TileSheets},
+    components::tiles::{MapPosition, SheetSprite},
+    entities::MonsterKind,
+    systems::{
+        pathfinding::astar::MovementRules,
+        tile_map::{
+            grid::MapSize,
+            level::{LevelAsset, LevelMonster, LevelTile},
+        },
+    },
+};
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    log::warn,
+    prelude::{FromWorld, World, default},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, path::Path};
+
+// Imports maps made in Tiled (https://www.mapeditor.org), saved either as
+// TMX (XML) or TMJ (JSON) with CSV tile layer data.
+//
+// Tile layers become `Layer(n)` in the order they appear. Tiles on the first
+// layer are walkable floor; a layer can override that with a `blocking` (bool)
+// or `cost` (int) custom property. Objects in object layers apply the same
+// properties to the ground tiles they cover, and an object whose class or
+// `spawn` property is `player` or `monster` marks a spawn point. Monster
+// spawns name their kind in a `monster` property ("rat" or "monster_rat");
+// tile objects draw their gid instead of the kind's own sprite.
+//
+// Map properties `diagonal_movement` (bool), `diagonal_cost` (int, percent of
+// an orthogonal step) and `corner_cutting` (bool) set the movement rules.
+
+const FLIP_FLAGS: u32 = 0xE000_0000;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Xml(roxmltree::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiledError::Io(err) => write!(f, "could not read Tiled map: {err}"),
+            TiledError::Json(err) => write!(f, "invalid TMJ: {err}"),
+            TiledError::Xml(err) => write!(f, "invalid TMX: {err}"),
+            TiledError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<std::io::Error> for TiledError {
+    fn from(err: std::io::Error) -> Self {
+        TiledError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(err: serde_json::Error) -> Self {
+        TiledError::Json(err)
+    }
+}
+
+impl From<roxmltree::Error> for TiledError {
+    fn from(err: roxmltree::Error) -> Self {
+        TiledError::Xml(err)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> TiledError {
+    TiledError::Invalid(message.into())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+type Properties = HashMap<String, Property>;
+
+#[derive(Debug, Clone, Default)]
+struct Tileset {
+    columns: u32,
+    image: String,
+}
+
+#[derive(Debug, Clone)]
+enum TilesetRef {
+    Inline(Tileset),
+    External(String),
+}
+
+#[derive(Debug, Clone)]
+struct Object {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    gid: Option<u32>,
+    class: String,
+    properties: Properties,
+}
+
+#[derive(Debug, Clone)]
+enum Layer {
+    Tiles {
+        data: Vec<u32>,
+        properties: Properties,
+    },
+    Objects(Vec<Object>),
+}
+
+#[derive(Debug, Clone)]
+struct Map {
+    width: usize,
+    height: usize,
+    tile_width: f64,
+    tile_height: f64,
+    tilesets: Vec<(u32, TilesetRef)>,
+    layers: Vec<Layer>,
+    properties: Properties,
+}
+
+// TMJ / TSJ
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    gid: Option<u32>,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    data: Option<serde_json::Value>,
+    encoding: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    firstgid: Option<u32>,
+    source: Option<String>,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    image: String,
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: usize,
+    height: usize,
+    tilewidth: f64,
+    tileheight: f64,
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<JsonLayer>,
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+fn json_properties(properties: Vec<JsonProperty>) -> Properties {
+    properties
+        .into_iter()
+        .filter_map(|p| {
+            let value = match (p.kind.as_str(), p.value) {
+                ("bool", serde_json::Value::Bool(b)) => Property::Bool(b),
+                ("int", serde_json::Value::Number(n)) => Property::Int(n.as_i64()?),
+                ("float", serde_json::Value::Number(n)) => Property::Float(n.as_f64()?),
+                (_, serde_json::Value::String(s)) => Property::String(s),
+                (_, serde_json::Value::Bool(b)) => Property::Bool(b),
+                (_, serde_json::Value::Number(n)) => match n.as_i64() {
+                    Some(i) => Property::Int(i),
+                    None => Property::Float(n.as_f64()?),
+                },
+                _ => return None,
+            };
+            Some((p.name, value))
+        })
+        .collect()
+}
+
+fn json_layers(layers: Vec<JsonLayer>, out: &mut Vec<Layer>) -> Result<(), TiledError> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                if layer.encoding.as_deref().is_some_and(|e| e != "csv") {
+                    return Err(invalid(
+                        "only CSV tile layer data is supported, change the layer format in Tiled",
+                    ));
+                }
+                let data = serde_json::from_value::<Vec<u32>>(
+                    layer
+                        .data
+                        .ok_or_else(|| invalid("tile layer without data"))?,
+                )?;
+                out.push(Layer::Tiles {
+                    data,
+                    properties: json_properties(layer.properties),
+                });
+            }
+            "objectgroup" => out.push(Layer::Objects(
+                layer
+                    .objects
+                    .into_iter()
+                    .map(|o| Object {
+                        x: o.x,
+                        y: o.y,
+                        width: o.width,
+                        height: o.height,
+                        gid: o.gid,
+                        class: if o.class.is_empty() { o.kind } else { o.class },
+                        properties: json_properties(o.properties),
+                    })
+                    .collect(),
+            )),
+            "group" => json_layers(layer.layers, out)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_tmj(bytes: &[u8]) -> Result<Map, TiledError> {
+    let map: JsonMap = serde_json::from_slice(bytes)?;
+    if map.infinite {
+        return Err(invalid("infinite Tiled maps are not supported"));
+    }
+    let mut layers = Vec::new();
+    json_layers(map.layers, &mut layers)?;
+    let tilesets = map
+        .tilesets
+        .into_iter()
+        .map(|t| {
+            let first_gid = t.firstgid.unwrap_or(1);
+            let tileset = match t.source {
+                Some(source) => TilesetRef::External(source),
+                None => TilesetRef::Inline(Tileset {
+                    columns: t.columns,
+                    image: t.image,
+                }),
+            };
+            (first_gid, tileset)
+        })
+        .collect();
+
+    Ok(Map {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        tilesets,
+        layers,
+        properties: json_properties(map.properties),
+    })
+}
+
+fn parse_tsj(bytes: &[u8]) -> Result<Tileset, TiledError> {
+    let tileset: JsonTileset = serde_json::from_slice(bytes)?;
+    Ok(Tileset {
+        columns: tileset.columns,
+        image: tileset.image,
+    })
+}
+
+// TMX / TSX
+
+fn xml_attr<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, TiledError> {
+    node.attribute(name)
+        .ok_or_else(|| invalid(format!("<{}> is missing '{name}'", node.tag_name().name())))?
+        .parse()
+        .map_err(|_| {
+            invalid(format!(
+                "<{}> has an invalid '{name}'",
+                node.tag_name().name()
+            ))
+        })
+}
+
+fn xml_attr_or<T: std::str::FromStr>(node: roxmltree::Node, name: &str, default: T) -> T {
+    node.attribute(name)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn xml_properties(node: roxmltree::Node) -> Properties {
+    node.children()
+        .filter(|n| n.has_tag_name("properties"))
+        .flat_map(|n| n.children().filter(|p| p.has_tag_name("property")))
+        .filter_map(|p| {
+            let name = p.attribute("name")?.to_string();
+            let raw = p.attribute("value").or_else(|| p.text()).unwrap_or("");
+            let value = match p.attribute("type").unwrap_or("string") {
+                "bool" => Property::Bool(raw == "true"),
+                "int" => Property::Int(raw.parse().ok()?),
+                "float" => Property::Float(raw.parse().ok()?),
+                _ => Property::String(raw.to_string()),
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+fn xml_tileset(node: roxmltree::Node) -> Result<Tileset, TiledError> {
+    let image = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .ok_or_else(|| invalid("tileset has no image"))?;
+    Ok(Tileset {
+        columns: xml_attr(node, "columns")?,
+        image: xml_attr(image, "source")?,
+    })
+}
+
+fn xml_layers(node: roxmltree::Node, out: &mut Vec<Layer>) -> Result<(), TiledError> {
+    for child in node.children().filter(|n| n.is_element()) {
+        match child.tag_name().name() {
+            "layer" => {
+                let data = child
+                    .children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or_else(|| invalid("tile layer without data"))?;
+                if data.attribute("encoding") != Some("csv") {
+                    return Err(invalid(
+                        "only CSV tile layer data is supported, change the layer format in Tiled",
+                    ));
+                }
+                let data = data
+                    .text()
+                    .unwrap_or("")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid("tile layer has an invalid gid"))?;
+                out.push(Layer::Tiles {
+                    data,
+                    properties: xml_properties(child),
+                });
+            }
+            "objectgroup" => {
+                let objects = child
+                    .children()
+                    .filter(|n| n.has_tag_name("object"))
+                    .map(|o| {
+                        Ok(Object {
+                            x: xml_attr(o, "x")?,
+                            y: xml_attr(o, "y")?,
+                            width: xml_attr_or(o, "width", 0.0),
+                            height: xml_attr_or(o, "height", 0.0),
+                            gid: o.attribute("gid").and_then(|g| g.parse().ok()),
+                            class: o
+                                .attribute("class")
+                                .or_else(|| o.attribute("type"))
+                                .unwrap_or("")
+                                .to_string(),
+                            properties: xml_properties(o),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, TiledError>>()?;
+                out.push(Layer::Objects(objects));
+            }
+            "group" => xml_layers(child, out)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_tmx(text: &str) -> Result<Map, TiledError> {
+    let document = roxmltree::Document::parse(text)?;
+    let root = document.root_element();
+    if !root.has_tag_name("map") {
+        return Err(invalid("TMX root element is not <map>"));
+    }
+    if root.attribute("infinite") == Some("1") {
+        return Err(invalid("infinite Tiled maps are not supported"));
+    }
+
+    let tilesets = root
+        .children()
+        .filter(|n| n.has_tag_name("tileset"))
+        .map(|t| {
+            let first_gid = xml_attr_or(t, "firstgid", 1);
+            let tileset = match t.attribute("source") {
+                Some(source) => TilesetRef::External(source.to_string()),
+                None => TilesetRef::Inline(xml_tileset(t)?),
+            };
+            Ok((first_gid, tileset))
+        })
+        .collect::<Result<Vec<_>, TiledError>>()?;
+    let mut layers = Vec::new();
+    xml_layers(root, &mut layers)?;
+
+    Ok(Map {
+        width: xml_attr(root, "width")?,
+        height: xml_attr(root, "height")?,
+        tile_width: xml_attr(root, "tilewidth")?,
+        tile_height: xml_attr(root, "tileheight")?,
+        tilesets,
+        layers,
+        properties: xml_properties(root),
+    })
+}
+
+fn parse_tsx(text: &str) -> Result<Tileset, TiledError> {
+    let document = roxmltree::Document::parse(text)?;
+    xml_tileset(document.root_element())
+}
+
+// Conversion
+
+struct ResolvedTileset {
+    first_gid: u32,
+    columns: u32,
+    sheet: SheetId,
+}
+
+fn resolve_sprite(tilesets: &[ResolvedTileset], gid: u32) -> Result<SheetSprite, TiledError> {
+    let gid = gid & !FLIP_FLAGS;
+    let tileset = tilesets
+        .iter()
+        .rev()
+        .find(|t| t.first_gid <= gid)
+        .ok_or_else(|| invalid(format!("gid {gid} is not in any tileset")))?;
+    let local = gid - tileset.first_gid;
+    // `SheetSprite` takes the row as `tilesheet_x` and the column as `tilesheet_y`
+    Ok(SheetSprite {
+        tilesheet: tileset.sheet.clone(),
+        tilesheet_x: local / tileset.columns,
+        tilesheet_y: local % tileset.columns,
+    })
+}
+
+fn bool_property(properties: &Properties, name: &str) -> Option<bool> {
+    match properties.get(name)? {
+        Property::Bool(b) => Some(*b),
+        Property::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn u32_property(properties: &Properties, name: &str) -> Option<u32> {
+    match properties.get(name)? {
+        Property::Int(i) => u32::try_from(*i).ok(),
+        Property::Float(f) => Some(f.max(0.0) as u32),
+        Property::String(s) => s.parse().ok(),
+        Property::Bool(_) => None,
+    }
+}
+
+fn movement_rules(properties: &Properties) -> Option<MovementRules> {
+    let mut rules = MovementRules {
+        diagonals: bool_property(properties, "diagonal_movement")?,
+        ..default()
+    };
+    if let Some(cost) = u32_property(properties, "diagonal_cost") {
+        rules.diagonal_cost = cost;
+    }
+    if let Some(corner_cutting) = bool_property(properties, "corner_cutting") {
+        rules.corner_cutting = corner_cutting;
+    }
+    Some(rules)
+}
+
+fn spawn_kind(object: &Object) -> Option<&str> {
+    match object.properties.get("spawn") {
+        Some(Property::String(kind)) => Some(kind.as_str()),
+        _ => Some(object.class.as_str()).filter(|c| !c.is_empty()),
+    }
+}
+
+fn build_level(
+    map: Map,
+    tilesets: Vec<(u32, Tileset)>,
+    sheets: &TileSheets,
+) -> Result<LevelAsset, TiledError> {
+    if map.width == 0 || map.height == 0 {
+        return Err(invalid("Tiled map has no tiles"));
+    }
+    let mut resolved = tilesets
+        .into_iter()
+        .map(|(first_gid, tileset)| {
+            let sheet = sheets.find_image(&tileset.image).ok_or_else(|| {
+                invalid(format!(
+                    "tileset image '{}' is not a known sheet",
+                    tileset.image
+                ))
+            })?;
+            if tileset.columns == 0 {
+                return Err(invalid(format!(
+                    "tileset '{}' has no columns",
+                    tileset.image
+                )));
+            }
+            Ok(ResolvedTileset {
+                first_gid,
+                columns: tileset.columns,
+                sheet,
+            })
+        })
+        .collect::<Result<Vec<_>, TiledError>>()?;
+    resolved.sort_by_key(|t| t.first_gid);
+
+    let size = MapSize {
+        width: map.width,
+        height: map.height,
+    };
+    // Tiled rows grow downwards, map y grows upwards
+    let position_of = |column: usize, row: usize| MapPosition {
+        x: column,
+        y: size.height - 1 - row,
+    };
+
+    let mut level = LevelAsset {
+        size,
+        tiles: Vec::new(),
+        player_spawn: None,
+        monsters: Vec::new(),
+        movement: movement_rules(&map.properties),
+    };
+    // Index of the lowest tile at each position, which object properties apply to
+    let mut ground: HashMap<MapPosition, usize> = HashMap::new();
+    let mut tile_layer = 0;
+    let mut objects = Vec::new();
+
+    for layer in map.layers {
+        match layer {
+            Layer::Tiles { data, properties } => {
+                if data.len() != size.width * size.height {
+                    return Err(invalid(format!(
+                        "tile layer has {} tiles, expected {}",
+                        data.len(),
+                        size.width * size.height
+                    )));
+                }
+                let blocking = bool_property(&properties, "blocking").unwrap_or(false);
+                let walk_cost = u32_property(&properties, "cost")
+                    .or((tile_layer == 0 && !blocking).then_some(1))
+                    .filter(|_| !blocking);
+                for (i, gid) in data.into_iter().enumerate() {
+                    if gid == 0 {
+                        continue;
+                    }
+                    let position = position_of(i % size.width, i / size.width);
+                    ground.entry(position).or_insert(level.tiles.len());
+                    level.tiles.push(LevelTile {
+                        position,
+                        layer: tile_layer,
+                        sprite: resolve_sprite(&resolved, gid)?,
+                        walk_cost,
+                        blocking,
+                        door: bool_property(&properties, "door").unwrap_or(false),
+                    });
+                }
+                tile_layer += 1;
+            }
+            Layer::Objects(layer_objects) => objects.extend(layer_objects),
+        }
+    }
+
+    for object in objects {
+        let column = (object.x / map.tile_width).floor().max(0.0) as usize;
+        // Tile objects are anchored at their bottom-left corner
+        let top = match object.gid {
+            Some(_) if object.height > 0.0 => object.y - object.height,
+            Some(_) => object.y - map.tile_height,
+            None => object.y,
+        };
+        let row = (top / map.tile_height).floor().max(0.0) as usize;
+        if column >= size.width || row >= size.height {
+            warn!(
+                "Ignoring Tiled object outside the map at ({}, {})",
+                object.x, object.y
+            );
+            continue;
+        }
+
+        match spawn_kind(&object) {
+            Some("player") => {
+                level.player_spawn = Some(position_of(column, row));
+                continue;
+            }
+            Some("monster") => {
+                let kind = match object.properties.get("monster") {
+                    Some(Property::String(name)) => MonsterKind::from_name(name)
+                        .ok_or_else(|| invalid(format!("unknown monster kind '{name}'")))?,
+                    _ => return Err(invalid("monster spawn has no `monster` kind property")),
+                };
+                let sprite = object
+                    .gid
+                    .map(|gid| resolve_sprite(&resolved, gid))
+                    .transpose()?;
+                level.monsters.push(LevelMonster {
+                    position: position_of(column, row),
+                    kind,
+                    sprite,
+                });
+                continue;
+            }
+            _ => {}
+        }
+
+        let blocking = bool_property(&object.properties, "blocking");
+        let walk_cost = u32_property(&object.properties, "cost");
+        let door = bool_property(&object.properties, "door");
+        let columns = ((object.width / map.tile_width).ceil() as usize).max(1);
+        let rows = ((object.height / map.tile_height).ceil() as usize).max(1);
+        for r in row..(row + rows).min(size.height) {
+            for c in column..(column + columns).min(size.width) {
+                let Some(&index) = ground.get(&position_of(c, r)) else {
+                    continue;
+                };
+                let tile = &mut level.tiles[index];
+                if let Some(blocking) = blocking {
+                    tile.blocking = blocking;
+                }
+                if let Some(cost) = walk_cost {
+                    tile.walk_cost = Some(cost);
+                }
+                if let Some(door) = door {
+                    tile.door = door;
+                }
+            }
+        }
+    }
+
+    Ok(level)
+}
+
+/// Loads Tiled maps, matching their tileset images against the sheet
+/// manifest.
+pub struct TiledLevelLoader {
+    sheets: TileSheets,
+}
+
+impl FromWorld for TiledLevelLoader {
+    fn from_world(world: &mut World) -> Self {
+        TiledLevelLoader {
+            sheets: world
+                .get_resource::<TileSheets>()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl AssetLoader for TiledLevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = TiledError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<LevelAsset, TiledError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let is_json = load_context
+            .path()
+            .extension()
+            .is_some_and(|ext| ext == "tmj" || ext == "json");
+        let map = if is_json {
+            parse_tmj(&bytes)?
+        } else {
+            parse_tmx(std::str::from_utf8(&bytes).map_err(|e| invalid(e.to_string()))?)?
+        };
+
+        let directory = load_context
+            .path()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut tilesets = Vec::new();
+        for (first_gid, tileset) in &map.tilesets {
+            let tileset = match tileset {
+                TilesetRef::Inline(tileset) => tileset.clone(),
+                TilesetRef::External(source) => {
+                    let bytes = load_context
+                        .read_asset_bytes(directory.join(source))
+                        .await
+                        .map_err(|e| invalid(format!("could not read tileset '{source}': {e}")))?;
+                    if source.ends_with(".tsj") || source.ends_with(".json") {
+                        parse_tsj(&bytes)?
+                    } else {
+                        parse_tsx(std::str::from_utf8(&bytes).map_err(|e| invalid(e.to_string()))?)?
+                    }
+                }
+            };
+            tilesets.push((*first_gid, tileset));
+        }
+
+        build_level(map, tilesets, &self.sheets)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMJ: &str = r#"{
+        "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+        "tilesets": [
+            { "firstgid": 1, "columns": 16, "image": "../tilesheets/tiny_dungeon_world.png" },
+            { "firstgid": 305, "columns": 16, "image": "../tilesheets/tiny_dungeon_monsters.png" }
+        ],
+        "layers": [
+            { "type": "tilelayer", "data": [1, 2, 3, 17, 18, 0] },
+            { "type": "tilelayer", "data": [0, 0, 0, 0, 0, 20],
+              "properties": [{ "name": "blocking", "type": "bool", "value": true }] },
+            { "type": "objectgroup", "objects": [
+                { "x": 0, "y": 0, "width": 16, "height": 16, "class": "",
+                  "properties": [{ "name": "cost", "type": "int", "value": 3 }] },
+                { "x": 16, "y": 32, "width": 16, "height": 16, "gid": 322, "type": "monster",
+                  "properties": [{ "name": "monster", "type": "string", "value": "monster_bat" }] },
+                { "x": 8, "y": 8, "point": true, "properties": [{ "name": "spawn", "type": "string", "value": "player" }] }
+            ] }
+        ]
+    }"#;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map width="2" height="1" tilewidth="16" tileheight="16" infinite="0">
+            <properties><property name="diagonal_movement" type="bool" value="true"/></properties>
+            <tileset firstgid="1" columns="16">
+                <image source="tiny_dungeon_world.png" width="256" height="304"/>
+            </tileset>
+            <layer name="floor" width="2" height="1">
+                <data encoding="csv">1,2</data>
+            </layer>
+            <objectgroup>
+                <object x="16" y="0" width="16" height="16">
+                    <properties><property name="blocking" type="bool" value="true"/></properties>
+                </object>
+            </objectgroup>
+        </map>"#;
+
+    fn sheets() -> TileSheets {
+        TileSheets::parse(include_str!("../../../assets/tilesheets/sheets.ron")).unwrap()
+    }
+
+    fn inline_tilesets(map: &Map) -> Vec<(u32, Tileset)> {
+        map.tilesets
+            .iter()
+            .map(|(gid, t)| match t {
+                TilesetRef::Inline(t) => (*gid, t.clone()),
+                TilesetRef::External(_) => panic!("external tileset in test map"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn imports_tmj_layers_sprites_and_objects() {
+        let map = parse_tmj(TMJ.as_bytes()).unwrap();
+        let tilesets = inline_tilesets(&map);
+        let level = build_level(map, tilesets, &sheets()).unwrap();
+
+        // gid 17 is the first tile of the second sheet row, on the bottom map row
+        let tile = level
+            .tiles
+            .iter()
+            .find(|t| t.position == MapPosition { x: 0, y: 0 })
+            .unwrap();
+        assert_eq!((tile.sprite.tilesheet_x, tile.sprite.tilesheet_y), (1, 0));
+        assert_eq!(tile.walk_cost, Some(1));
+
+        let wall = level.tiles.iter().find(|t| t.layer == 1).unwrap();
+        assert!(wall.blocking);
+        assert_eq!(wall.position, MapPosition { x: 2, y: 0 });
+
+        let costly = level
+            .tiles
+            .iter()
+            .find(|t| t.position == MapPosition { x: 0, y: 1 })
+            .unwrap();
+        assert_eq!(costly.walk_cost, Some(3));
+
+        assert_eq!(level.player_spawn, Some(MapPosition { x: 0, y: 1 }));
+        let monster = &level.monsters[0];
+        assert_eq!(monster.position, MapPosition { x: 1, y: 0 });
+        assert_eq!(monster.kind, MonsterKind::Bat);
+        let sprite = monster.sprite.as_ref().unwrap();
+        assert_eq!(sprite.tilesheet, SheetId::new("monsters"));
+        assert_eq!((sprite.tilesheet_x, sprite.tilesheet_y), (1, 1));
+        assert_eq!(level.movement, None);
+    }
+
+    #[test]
+    fn imports_tmx_with_blocking_objects() {
+        let map = parse_tmx(TMX).unwrap();
+        let tilesets = inline_tilesets(&map);
+        let level = build_level(map, tilesets, &sheets()).unwrap();
+
+        assert_eq!(level.tiles.len(), 2);
+        assert!(!level.tiles[0].blocking);
+        assert!(level.tiles[1].blocking);
+        assert_eq!(level.tiles[1].sprite.tilesheet_y, 1);
+        assert_eq!(level.movement, Some(MovementRules::eight_way()));
+    }
+
+    #[test]
+    fn rejects_unknown_tileset_images() {
+        let map = parse_tmx(&TMX.replace("tiny_dungeon_world.png", "other.png")).unwrap();
+        let tilesets = inline_tilesets(&map);
+        assert!(build_level(map, tilesets, &sheets()).is_err());
+    }
+}
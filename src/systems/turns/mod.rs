@@ -0,0 +1,173 @@
+use crate::components::{
+    attributes::{Energy, Moving},
+    basic::{Faction, Player, TakingTurn, Visible},
+    tiles::{MapPosition, Target},
+};
+use crate::events::AttackEvent;
+use crate::systems::{
+    game_input::actions::{Action, ActionState},
+    pathfinding::Movement,
+    tile_map::grid::TileMap,
+};
+use bevy::prelude::*;
+
+/// Energy an actor spends on a single action.
+pub const ACTION_COST: u32 = 100;
+
+/// Ends `entity`'s turn after it has performed an action.
+pub fn spend_turn(commands: &mut Commands, entity: Entity, energy: &mut Energy) {
+    energy.current = energy.current.saturating_sub(ACTION_COST);
+    commands.entity(entity).remove::<TakingTurn>();
+}
+
+/// Hands out turns once nobody is still busy with theirs. Energy is added in
+/// ticks until at least one actor can afford an action, then every actor that
+/// can gets a turn.
+pub fn advance_turns(
+    mut commands: Commands,
+    busy: Query<(), With<TakingTurn>>,
+    mut actors: Query<(Entity, &mut Energy)>,
+) {
+    if !busy.is_empty() {
+        return;
+    }
+
+    let ticks = actors
+        .iter()
+        .filter(|(_, energy)| energy.current >= ACTION_COST || energy.speed > 0)
+        .map(|(_, energy)| {
+            ACTION_COST
+                .saturating_sub(energy.current)
+                .div_ceil(energy.speed.max(1))
+        })
+        .min();
+    let Some(ticks) = ticks else {
+        return;
+    };
+
+    for (entity, mut energy) in actors.iter_mut() {
+        energy.current += energy.speed * ticks;
+        if energy.current >= ACTION_COST {
+            commands.entity(entity).insert(TakingTurn);
+        }
+    }
+}
+
+type PlayerActor<'a> = (
+    Entity,
+    &'a mut MapPosition,
+    &'a mut Energy,
+    &'a mut Target,
+    &'a Faction,
+    Option<&'a Movement>,
+);
+
+/// Blocks the game until the player picks an action. Each move action steps
+/// one tile, or attacks whatever hostile actor stands there, and waiting
+/// passes a turn; all of them cancel any auto-move in progress. Diagonal moves
+/// follow the same rules as pathfinding.
+#[allow(clippy::too_many_arguments)]
+pub fn player_turn(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    tile_map: Option<Res<TileMap>>,
+    map_rules: Res<Movement>,
+    mut player: Query<PlayerActor, (With<Player>, With<TakingTurn>)>,
+    actors: Query<(Entity, &MapPosition, &Faction), Without<Player>>,
+    mut attacks: EventWriter<AttackEvent>,
+) {
+    let (Some(tile_map), Ok((entity, mut map_pos, mut energy, mut target, faction, rules))) =
+        (tile_map, player.single_mut())
+    else {
+        return;
+    };
+    let rules = rules.unwrap_or(&map_rules);
+
+    let step = Action::MOVES
+        .into_iter()
+        .find(|(action, _)| actions.just_pressed(*action))
+        .map(|(_, step)| step);
+
+    if let Some((dx, dy)) = step {
+        let (Some(x), Some(y)) = (
+            map_pos.x.checked_add_signed(dx),
+            map_pos.y.checked_add_signed(dy),
+        ) else {
+            return;
+        };
+        let destination = MapPosition { x, y };
+        if !rules
+            .steps(&*tile_map, *map_pos)
+            .iter()
+            .any(|(step, _)| *step == destination)
+        {
+            return;
+        }
+        let occupant = actors.iter().find(|(_, pos, _)| **pos == destination);
+        match occupant {
+            Some((defender, _, other)) if faction.is_hostile_to(*other) => {
+                attacks.write(AttackEvent {
+                    attacker: entity,
+                    defender,
+                });
+            }
+            // Bumping into walls or friends doesn't cost a turn
+            Some(_) => return,
+            None if tile_map.walk_cost(destination).is_none() => return,
+            None => *map_pos = destination,
+        }
+    } else if !actions.just_pressed(Action::Wait) {
+        return;
+    }
+
+    commands.entity(entity).remove::<Moving>();
+    target.path = None;
+    target.position = None;
+    spend_turn(&mut commands, entity, &mut energy);
+}
+
+type AutoMovingPlayer = (With<Player>, With<Moving>);
+type NewlySeen = (Without<Player>, Changed<Visible>);
+
+/// Stops the player's auto-move as soon as a hostile actor comes into view.
+/// Hostiles that were already in sight when the move started, like the one
+/// it walks up to, don't stop it.
+pub fn interrupt_auto_move(
+    mut commands: Commands,
+    player: Query<(Entity, &Faction), AutoMovingPlayer>,
+    actors: Query<(&Visible, &Faction), NewlySeen>,
+) {
+    let Ok((entity, faction)) = player.single() else {
+        return;
+    };
+    if actors
+        .iter()
+        .any(|(visible, other)| *visible == Visible::InView && faction.is_hostile_to(*other))
+    {
+        commands.entity(entity).remove::<Moving>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_hostiles_coming_into_view_interrupt_auto_moves() {
+        let mut world = World::new();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(interrupt_auto_move);
+        let player = world.spawn((Player, Faction::Heroes)).id();
+        world.spawn((Visible::InView, Faction::Monsters));
+        let newcomer = world.spawn((Visible::Remembered, Faction::Monsters)).id();
+        schedule.run(&mut world);
+
+        world.entity_mut(player).insert(Moving::default());
+        schedule.run(&mut world);
+        assert!(world.get::<Moving>(player).is_some());
+
+        *world.get_mut::<Visible>(newcomer).unwrap() = Visible::InView;
+        schedule.run(&mut world);
+        assert!(world.get::<Moving>(player).is_none());
+    }
+}
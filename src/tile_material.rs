@@ -0,0 +1,66 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+const SHADER_ASSET_PATH: &str = "shaders/tile_material.wgsl";
+
+/// The low bits of a tile's `MeshTag` pick the cell of its sheet, counting
+/// row by row from the top left. The high bits are the flags below; the
+/// layout is shared with `tile_material.wgsl`.
+pub const TAG_INDEX_MASK: u32 = 0x00ff_ffff;
+/// Terrain out of view that the player has seen before, drawn dimmed.
+pub const TAG_REMEMBERED: u32 = 1 << 24;
+/// Tile under the cursor.
+pub const TAG_HIGHLIGHTED: u32 = 1 << 25;
+
+/// Draws one cell of a whole tilesheet. Every tile from the same sheet shares
+/// a single `TileMaterial` and tells it which cell to show through its
+/// `MeshTag`, so they can all be drawn together.
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TileMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub texture: Handle<Image>,
+    #[uniform(2)]
+    pub atlas: AtlasLayout,
+}
+
+pub use atlas::AtlasLayout;
+
+// `ShaderType` generates a size check per field next to the struct, which
+// rustc reports as unused in a binary crate.
+#[allow(dead_code)]
+mod atlas {
+    use bevy::{prelude::*, render::render_resource::ShaderType};
+
+    /// Where the cells of a sheet are, in texture coordinates.
+    #[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
+    pub struct AtlasLayout {
+        pub columns: u32,
+        /// Top left corner of the first cell.
+        pub origin: Vec2,
+        /// Distance from one cell to the next, gap included.
+        pub stride: Vec2,
+        /// Size of one cell.
+        pub size: Vec2,
+    }
+
+    impl AtlasLayout {
+        /// The cell at `row` and `column`.
+        pub fn cell(&self, row: u32, column: u32) -> Rect {
+            let min = self.origin + Vec2::new(column as f32, row as f32) * self.stride;
+            Rect::from_corners(min, min + self.size)
+        }
+    }
+}
+
+impl Material for TileMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}